@@ -2,19 +2,15 @@ use ufo3000::template::{
     WGPUFeatures,
 };
 
-/// Features and limits for DummyExample application.
+/// Features and limits for Cube application.
 pub struct CubeFeatures {}
 
 impl WGPUFeatures for CubeFeatures {
 
-    fn optional_features() -> wgpu::Features {
-        wgpu::Features::TIMESTAMP_QUERY
-    }
-
     fn required_features() -> wgpu::Features {
 
         if cfg!(not(target_arch = "wasm32")) {
-            wgpu::Features::empty()
+            wgpu::Features::TIMESTAMP_QUERY
             // wgpu::Features::PUSH_CONSTANTS |
             // wgpu::Features::WRITE_TIMESTAMP_INSIDE_PASSES
         }
@@ -41,3 +37,13 @@ impl WGPUFeatures for CubeFeatures {
         limits
     }
 }
+
+/// The fallback for adapters without timestamp queries.
+pub struct CubeFallbackFeatures {}
+
+impl WGPUFeatures for CubeFallbackFeatures {
+
+    fn required_limits() -> wgpu::Limits {
+        CubeFeatures::required_limits()
+    }
+}
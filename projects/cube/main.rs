@@ -7,12 +7,17 @@ use ufo3000::template::{
     Application,
     BasicLoop,
     Spawner,
-    run_loop,
+    RenderTarget,
+    ExitRequest,
+    run_loop_with_fallback,
 };
+#[cfg(not(target_arch = "wasm32"))]
+use ufo3000::template::{Loop, OffscreenLoop, run_offscreen_loop};
+use ufo3000::app_config::{AppConfig, RedrawMode};
 use ufo3000::screen::ScreenTexture;
 use ufo3000::camera::Camera;
-use ufo3000::texture::Texture as ATexture;
 use ufo3000::render_object::create_render_pass;
+use ufo3000::profiler::GpuProfiler;
 
 #[cfg(target_arch = "wasm32")]
 use ufo3000::template::OffscreenCanvasSetup;
@@ -22,6 +27,7 @@ use ufo3000::template::OffscreenCanvasSetup;
 struct CubeApp {
     screen: ScreenTexture, 
     camera: Camera,
+    profiler: GpuProfiler,
     render: bool,
 }
 
@@ -43,6 +49,7 @@ impl Application for CubeApp {
         Self {
             screen: ScreenTexture::init(&configuration.device, &configuration.sc_desc, true),
             camera,
+            profiler: GpuProfiler::init(&configuration.device, &configuration.queue, 4),
             render: true,
         }
     }
@@ -51,7 +58,7 @@ impl Application for CubeApp {
     fn render(&mut self,
               device: &wgpu::Device,
               queue: &mut wgpu::Queue,
-              surface: &wgpu::Surface,
+              target: &RenderTarget,
               sc_desc: &wgpu::SurfaceConfiguration,
               #[cfg(target_arch = "wasm32")]
               offscreen_canvas_setup: &OffscreenCanvasSetup,
@@ -59,33 +66,39 @@ impl Application for CubeApp {
 
         if self.render {
 
-            // Acquire screen.
-            self.screen.acquire_screen_texture(
-                device,
-                sc_desc,
-                surface
-                );
+            // Acquire screen. Skip the frame if the surface isn't available.
+            if !self.screen.acquire_screen_texture(device, sc_desc, target) {
+                return;
+            }
 
             // Create view.
-            let view = self.screen.surface_texture.as_ref().unwrap().texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let view = self.screen.view.as_ref().unwrap();
 
             // If there is nothing to draw, this must be executed.
             let mut cube_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Cube encoder") });
             {
+                let scope = self.profiler.scope("cube");
                 let _render_pass = create_render_pass(
                     &mut cube_encoder,
-                    &view,
-                    self.screen.depth_texture.as_ref().unwrap(),
+                    view,
+                    self.screen.depth_texture.as_ref(),
                     true,
                     &Some(wgpu::Color {
                         r: 1.0,
                         g: 0.0,
                         b: 0.0,
                         a: 1.0,
-                    })
+                    }),
+                    Some(&scope)
                     );
             }
+            self.profiler.resolve(&mut cube_encoder);
             queue.submit(Some(cube_encoder.finish()));
+            self.profiler.end_frame();
+
+            if self.profiler.poll_results(device) {
+                log::debug!("cube pass: {:?} ms", self.profiler.timing("cube"));
+            }
 
             // Prepare rendering.
             #[cfg(not(target_arch = "wasm32"))]
@@ -103,10 +116,9 @@ impl Application for CubeApp {
     }
 
     /// Resize window.
-    fn resize(&mut self, device: &wgpu::Device, sc_desc: &wgpu::SurfaceConfiguration, _new_size: winit::dpi::PhysicalSize<u32>) {
+    fn resize(&mut self, _device: &wgpu::Device, sc_desc: &wgpu::SurfaceConfiguration, _new_size: winit::dpi::PhysicalSize<u32>) {
 
-        // TODO: add this functionality to the Screen.
-        self.screen.depth_texture = Some(ATexture::create_depth_texture(device, sc_desc, Some("depth-texture")));
+        // The depth texture is recreated by the ScreenTexture.
         self.camera.resize(sc_desc.width as f32, sc_desc.height as f32);
     }
 
@@ -115,6 +127,11 @@ impl Application for CubeApp {
 
     }
 
+    /// Exit on request or when the surface has run out of memory.
+    fn should_exit(&mut self, _input: &InputCache, request: ExitRequest) -> bool {
+        request != ExitRequest::None || self.screen.out_of_memory()
+    }
+
     /// Exit.
     fn exit(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue, _input: &InputCache, _spawner: &Spawner) {
         log::info!("Exit.");
@@ -128,7 +145,20 @@ fn main() {
 
     log::info!("Hekotus from dummy_example.");
 
-    // Execute application.
-    run_loop::<CubeApp, BasicLoop, cube_features::CubeFeatures>(); 
+    // Render a few frames to png files without a window.
+    #[cfg(not(target_arch = "wasm32"))]
+    if std::env::args().any(|arg| arg == "--offscreen") {
+        let offscreen_loop = OffscreenLoop::init().with_frame_count(4);
+        run_offscreen_loop::<CubeApp, cube_features::CubeFallbackFeatures>(offscreen_loop, 512, 512);
+        return;
+    }
+
+    // The cube is static, so redraw only when something happens.
+    let config = AppConfig::default()
+        .with_title("cube")
+        .with_redraw_mode(RedrawMode::OnDemand);
+
+    // Execute application. Timestamp queries are dropped if the adapter doesn't support them.
+    run_loop_with_fallback::<CubeApp, BasicLoop, cube_features::CubeFeatures, cube_features::CubeFallbackFeatures>(config); 
 
 }
@@ -9,11 +9,13 @@ use ufo3000::template::{
     Application,
     BasicLoop,
     Spawner,
+    RenderTarget,
+    ExitRequest,
     run_loop,
 };
+use ufo3000::app_config::AppConfig;
 use ufo3000::screen::ScreenTexture;
 use ufo3000::camera::Camera;
-use ufo3000::texture::Texture as ATexture;
 // use ufo3000::render_object::*;
 
 // TODO: drop renderpass if there is nothing to draw.
@@ -50,21 +52,19 @@ impl Application for DummyExampleApp {
     fn render(&mut self,
               device: &wgpu::Device,
               _queue: &mut wgpu::Queue,
-              surface: &wgpu::Surface,
+              target: &RenderTarget,
               sc_desc: &wgpu::SurfaceConfiguration,
               _spawner: &Spawner) {
 
         if self.render {
 
-            // Acquire screen.
-            self.screen.acquire_screen_texture(
-                device,
-                sc_desc,
-                surface
-                );
+            // Acquire screen. Skip the frame if the surface isn't available.
+            if !self.screen.acquire_screen_texture(device, sc_desc, target) {
+                return;
+            }
 
             // Create view.
-            let _view = self.screen.surface_texture.as_ref().unwrap().texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let _view = self.screen.view.as_ref().unwrap();
 
             // Prepare rendering.
             self.screen.prepare_for_rendering();
@@ -78,10 +78,9 @@ impl Application for DummyExampleApp {
     }
 
     /// Resize window.
-    fn resize(&mut self, device: &wgpu::Device, sc_desc: &wgpu::SurfaceConfiguration, _new_size: winit::dpi::PhysicalSize<u32>) {
+    fn resize(&mut self, _device: &wgpu::Device, sc_desc: &wgpu::SurfaceConfiguration, _new_size: winit::dpi::PhysicalSize<u32>) {
 
-        // TODO: add this functionality to the Screen.
-        self.screen.depth_texture = Some(ATexture::create_depth_texture(device, sc_desc, Some("depth-texture")));
+        // The depth texture is recreated by the ScreenTexture.
         self.camera.resize(sc_desc.width as f32, sc_desc.height as f32);
     }
 
@@ -90,6 +89,11 @@ impl Application for DummyExampleApp {
 
     }
 
+    /// Exit on request or when the surface has run out of memory.
+    fn should_exit(&mut self, _input: &InputCache, request: ExitRequest) -> bool {
+        request != ExitRequest::None || self.screen.out_of_memory()
+    }
+
     /// Exit.
     fn exit(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue, _input: &InputCache, _spawner: &Spawner) {
         log::info!("Exit.");
@@ -104,6 +108,6 @@ fn main() {
     log::info!("Hekotus from dummy_example.");
 
     // Execute application.
-    run_loop::<DummyExampleApp, BasicLoop, dummy_features::DummyExampleFeatures>(); 
+    run_loop::<DummyExampleApp, BasicLoop, dummy_features::DummyExampleFeatures>(AppConfig::default().with_title("dummy_example")); 
 
 }
@@ -0,0 +1,289 @@
+use crate::template::{Key, SetupError};
+
+/// The preferred color space of the surface format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// A format with the Srgb suffix (the shader output is gamma encoded by the hardware).
+    Srgb,
+    /// A format without the Srgb suffix.
+    Linear,
+}
+
+/// How often BasicLoop redraws.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RedrawMode {
+    /// Redraw as fast as possible (ControlFlow::Poll).
+    Continuous,
+    /// Redraw only after window events, RedrawRequester::request_redraw, active input or when
+    /// Application::needs_redraw returns true. Otherwise the loop sleeps (ControlFlow::Wait).
+    OnDemand,
+    /// The same as OnDemand, but the loop wakes up at least once per duration
+    /// (ControlFlow::WaitUntil).
+    Periodic(std::time::Duration),
+}
+
+/// The window and surface settings of the application. Pass this to run_loop. The choices are
+/// validated against the surface capabilities and unsupported choices are replaced with
+/// supported ones.
+#[derive(Clone, Debug)]
+pub struct AppConfig {
+    title: String,
+    size: Option<winit::dpi::PhysicalSize<u32>>,
+    fullscreen: bool,
+    present_mode: wgpu::PresentMode,
+    color_space: ColorSpace,
+    format: Option<wgpu::TextureFormat>,
+    alpha_mode: wgpu::CompositeAlphaMode,
+    quit_key: Option<Key>,
+    redraw_mode: RedrawMode,
+    frame_stats_window: usize,
+    frame_stats_report: Option<std::time::Duration>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            title: "jihuu".to_owned(),
+            size: None,
+            fullscreen: false,
+            present_mode: wgpu::PresentMode::Fifo,
+            color_space: ColorSpace::Srgb,
+            format: None,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            quit_key: Some(Key::Q),
+            redraw_mode: RedrawMode::Continuous,
+            frame_stats_window: 240,
+            frame_stats_report: None,
+        }
+    }
+}
+
+impl AppConfig {
+
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.title = title.to_owned();
+        self
+    }
+
+    /// The initial inner size of the window. By default the size is chosen by winit.
+    pub fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.size = Some(winit::dpi::PhysicalSize::new(width, height));
+        self
+    }
+
+    /// Borderless fullscreen on the current monitor.
+    pub fn with_fullscreen(mut self, fullscreen: bool) -> Self {
+        self.fullscreen = fullscreen;
+        self
+    }
+
+    /// Falls back to Fifo if the surface doesn't support the present mode.
+    pub fn with_present_mode(mut self, present_mode: wgpu::PresentMode) -> Self {
+        self.present_mode = present_mode;
+        self
+    }
+
+    /// Shorthand for with_present_mode(AutoVsync) and with_present_mode(AutoNoVsync).
+    pub fn with_vsync(self, vsync: bool) -> Self {
+        self.with_present_mode(if vsync { wgpu::PresentMode::AutoVsync } else { wgpu::PresentMode::AutoNoVsync })
+    }
+
+    /// Prefer an sRGB or a linear surface format.
+    pub fn with_color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
+    /// Use this exact surface format if the surface supports it. Otherwise the color space is
+    /// used for choosing the format.
+    pub fn with_format(mut self, format: wgpu::TextureFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Falls back to the first supported alpha mode.
+    pub fn with_alpha_mode(mut self, alpha_mode: wgpu::CompositeAlphaMode) -> Self {
+        self.alpha_mode = alpha_mode;
+        self
+    }
+
+    /// The key that closes the application (see Application::should_exit). None disables the
+    /// quit key.
+    pub fn with_quit_key(mut self, quit_key: Option<Key>) -> Self {
+        self.quit_key = quit_key;
+        self
+    }
+
+    pub fn with_redraw_mode(mut self, redraw_mode: RedrawMode) -> Self {
+        self.redraw_mode = redraw_mode;
+        self
+    }
+
+    /// The number of frames in the rolling window of FrameStats.
+    pub fn with_frame_stats_window(mut self, frames: usize) -> Self {
+        self.frame_stats_window = frames;
+        self
+    }
+
+    /// Log a FrameStats summary every interval. None (the default) disables the reports.
+    pub fn with_frame_stats_report(mut self, interval: Option<std::time::Duration>) -> Self {
+        self.frame_stats_report = interval;
+        self
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn size(&self) -> Option<winit::dpi::PhysicalSize<u32>> {
+        self.size
+    }
+
+    pub fn fullscreen(&self) -> bool {
+        self.fullscreen
+    }
+
+    pub fn quit_key(&self) -> Option<Key> {
+        self.quit_key
+    }
+
+    pub fn redraw_mode(&self) -> RedrawMode {
+        self.redraw_mode
+    }
+
+    pub fn frame_stats_window(&self) -> usize {
+        self.frame_stats_window
+    }
+
+    pub fn frame_stats_report(&self) -> Option<std::time::Duration> {
+        self.frame_stats_report
+    }
+
+    /// Create the window builder.
+    pub fn window_builder(&self) -> winit::window::WindowBuilder {
+        let mut builder = winit::window::WindowBuilder::new().with_title(self.title.clone());
+        if let Some(size) = self.size {
+            builder = builder.with_inner_size(size);
+        }
+        if self.fullscreen {
+            builder = builder.with_fullscreen(Some(winit::window::Fullscreen::Borderless(None)));
+        }
+        builder
+    }
+
+    /// Create the surface configuration for the surface using the supported choices. Fails
+    /// with SetupError::UnsupportedSurface if the adapter can't present to the surface.
+    pub fn surface_configuration(&self,
+                                 surface: &wgpu::Surface,
+                                 adapter: &wgpu::Adapter,
+                                 size: winit::dpi::PhysicalSize<u32>) -> Result<wgpu::SurfaceConfiguration, SetupError> {
+
+        let capabilities = surface.get_capabilities(adapter);
+        if capabilities.alpha_modes.is_empty() {
+            return Err(SetupError::UnsupportedSurface);
+        }
+        let format = self.choose_format(&capabilities.formats)?;
+
+        // The other srgb variant of the format can be used for views if the adapter allows it.
+        let mut view_formats = Vec::new();
+        if adapter.get_downlevel_capabilities().flags.contains(wgpu::DownlevelFlags::SURFACE_VIEW_FORMATS) {
+            let other = if format.is_srgb() { format.remove_srgb_suffix() } else { format.add_srgb_suffix() };
+            if other != format {
+                view_formats.push(other);
+            }
+        }
+
+        Ok(wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: self.choose_present_mode(&capabilities.present_modes),
+            alpha_mode: self.choose_alpha_mode(&capabilities.alpha_modes),
+            view_formats,
+        })
+    }
+
+    /// The requested format, the first format of the preferred color space or the first format.
+    /// Fails if there are no formats (the surface isn't supported by the adapter).
+    pub fn choose_format(&self, formats: &[wgpu::TextureFormat]) -> Result<wgpu::TextureFormat, SetupError> {
+
+        if formats.is_empty() {
+            return Err(SetupError::UnsupportedSurface);
+        }
+
+        if let Some(format) = self.format {
+            if formats.contains(&format) {
+                return Ok(format);
+            }
+            log::warn!("Surface format {:?} isn't supported.", format);
+        }
+
+        let srgb = self.color_space == ColorSpace::Srgb;
+        match formats.iter().find(|f| f.is_srgb() == srgb) {
+            Some(format) => Ok(*format),
+            None => {
+                log::warn!("The surface doesn't support {:?} formats. Using {:?}.", self.color_space, formats[0]);
+                Ok(formats[0])
+            }
+        }
+    }
+
+    /// The requested present mode or Fifo. The auto modes are always supported.
+    pub fn choose_present_mode(&self, present_modes: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+        match self.present_mode {
+            wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync => self.present_mode,
+            mode if present_modes.contains(&mode) => mode,
+            mode => {
+                log::warn!("Present mode {:?} isn't supported. Using Fifo.", mode);
+                wgpu::PresentMode::Fifo
+            }
+        }
+    }
+
+    /// The requested alpha mode or the first supported alpha mode. Auto if there are no
+    /// supported alpha modes.
+    pub fn choose_alpha_mode(&self, alpha_modes: &[wgpu::CompositeAlphaMode]) -> wgpu::CompositeAlphaMode {
+        match self.alpha_mode {
+            wgpu::CompositeAlphaMode::Auto => wgpu::CompositeAlphaMode::Auto,
+            mode if alpha_modes.contains(&mode) => mode,
+            mode => {
+                let fallback = alpha_modes.first().copied().unwrap_or(wgpu::CompositeAlphaMode::Auto);
+                log::warn!("Alpha mode {:?} isn't supported. Using {:?}.", mode, fallback);
+                fallback
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::TextureFormat;
+
+    #[test]
+    fn format_fallbacks() {
+        let formats = [TextureFormat::Bgra8Unorm, TextureFormat::Bgra8UnormSrgb];
+        assert_eq!(AppConfig::default().choose_format(&formats).unwrap(), TextureFormat::Bgra8UnormSrgb);
+        assert_eq!(AppConfig::default().with_color_space(ColorSpace::Linear).choose_format(&formats).unwrap(), TextureFormat::Bgra8Unorm);
+        assert_eq!(AppConfig::default().with_format(TextureFormat::Rgba16Float).choose_format(&formats).unwrap(), TextureFormat::Bgra8UnormSrgb);
+        assert_eq!(AppConfig::default().choose_format(&[TextureFormat::Rgba16Float]).unwrap(), TextureFormat::Rgba16Float);
+    }
+
+    #[test]
+    fn unsupported_surface() {
+        assert!(matches!(AppConfig::default().choose_format(&[]), Err(SetupError::UnsupportedSurface)));
+        let config = AppConfig::default().with_alpha_mode(wgpu::CompositeAlphaMode::PreMultiplied);
+        assert_eq!(config.choose_alpha_mode(&[]), wgpu::CompositeAlphaMode::Auto);
+    }
+
+    #[test]
+    fn present_and_alpha_mode_fallbacks() {
+        let config = AppConfig::default()
+            .with_present_mode(wgpu::PresentMode::Mailbox)
+            .with_alpha_mode(wgpu::CompositeAlphaMode::PreMultiplied);
+        assert_eq!(config.choose_present_mode(&[wgpu::PresentMode::Fifo]), wgpu::PresentMode::Fifo);
+        assert_eq!(config.choose_alpha_mode(&[wgpu::CompositeAlphaMode::Opaque]), wgpu::CompositeAlphaMode::Opaque);
+        assert_eq!(config.with_vsync(false).choose_present_mode(&[]), wgpu::PresentMode::AutoNoVsync);
+    }
+}
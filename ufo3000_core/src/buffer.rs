@@ -1,6 +1,7 @@
-use crate::misc::Convert2Vec;
+use crate::misc::vec_from_bytes;
 use bytemuck::Pod;
 use wgpu::util::DeviceExt;
+use crate::readback::map_read;
 
 /// Create wgpu::buffer from data.
 pub fn buffer_from_data<T: Pod>(
@@ -37,17 +38,25 @@ pub fn buffer_from_data<T: Pod>(
 //    res
 //}
 
-/// Copy the content of the buffer into a vector.
-pub fn to_vec<T: Convert2Vec + std::clone::Clone + bytemuck::Pod + std::marker::Send>(
+/// Copy copy_size bytes starting from src_offset of the buffer into a vector. Both must be
+/// multiples of wgpu::COPY_BUFFER_ALIGNMENT. Awaits the mapping on every target (natively the
+/// device is polled until the copy is done). Use ReadbackPool for readbacks that don't poll the
+/// device and reuse the staging buffers.
+///
+/// Breaking change: to_vec used to block and is now async. Native sync callers can use
+/// to_vec_blocking.
+pub async fn to_vec<T: Pod>(
     device: &wgpu::Device,
     queue: &wgpu::Queue,
     buffer: &wgpu::Buffer,
-    _src_offset: wgpu::BufferAddress,
+    src_offset: wgpu::BufferAddress,
     copy_size: wgpu::BufferAddress,
     // _spawner: &Spawner,
     ) -> Vec<T> {
 
-    // TODO: Recycle staging buffers.
+    assert!(src_offset % wgpu::COPY_BUFFER_ALIGNMENT == 0 && copy_size % wgpu::COPY_BUFFER_ALIGNMENT == 0,
+            "to_vec: src_offset and copy_size must be multiples of COPY_BUFFER_ALIGNMENT.");
+
     let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
         label: None,
         size: copy_size,
@@ -56,23 +65,30 @@ pub fn to_vec<T: Convert2Vec + std::clone::Clone + bytemuck::Pod + std::marker::
     });
 
     let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-    encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, copy_size);
+    encoder.copy_buffer_to_buffer(buffer, src_offset, &staging_buffer, 0, copy_size);
     queue.submit(Some(encoder.finish()));
 
     
 
     let buffer_slice = staging_buffer.slice(..);
-    //++ let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
-    //++ let _ = buffer_slice.map_async(wgpu::MapMode::Read, true);
-    // let _ = buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
-    buffer_slice.map_async(wgpu::MapMode::Read, move |_| ());
-    device.poll(wgpu::Maintain::Wait);
+    map_read(device, buffer_slice).await.expect("Failed to map the staging buffer.");
 
-    // Wasm version crashes: DOMException.getMappedRange: Buffer not mapped.
     let data = buffer_slice.get_mapped_range().to_vec();
-    let res: Vec<T> = Convert2Vec::convert(&data);
+    let res: Vec<T> = vec_from_bytes(&data);
     drop(data);
     staging_buffer.unmap();
 
     res
 }
+
+/// The blocking version of to_vec for sync callers. Native only.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn to_vec_blocking<T: Pod>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    src_offset: wgpu::BufferAddress,
+    copy_size: wgpu::BufferAddress,
+    ) -> Vec<T> {
+    pollster::block_on(to_vec(device, queue, buffer, src_offset, copy_size))
+}
@@ -0,0 +1,183 @@
+use crate::input::{InputCache, InputState, Key};
+
+/// The smallest and the largest time scale.
+const MIN_TIME_SCALE: f64 = 1.0 / 64.0;
+const MAX_TIME_SCALE: f64 = 64.0;
+
+/// An accumulator based clock for fixed time step simulations. The real frame time is
+/// accumulated and consumed in fixed size ticks.
+#[derive(Clone, Debug)]
+pub struct FixedStepClock {
+    time_step: f64,
+    accumulator: f64,
+    max_substeps: u32,
+    time_scale: f64,
+    paused: bool,
+    pending_steps: u32,
+}
+
+impl FixedStepClock {
+
+    /// Create a clock with tick_rate ticks per second. At most max_substeps ticks are run per
+    /// frame, the rest of the accumulated time is dropped.
+    pub fn new(tick_rate: f64, max_substeps: u32) -> Self {
+        assert!(tick_rate > 0.0, "Tick rate must be > 0.");
+        assert!(max_substeps > 0, "Max substeps must be > 0.");
+        Self {
+            time_step: 1.0 / tick_rate,
+            accumulator: 0.0,
+            max_substeps,
+            time_scale: 1.0,
+            paused: false,
+            pending_steps: 0,
+        }
+    }
+
+    /// Accumulate the frame time (seconds) and return the number of ticks to run.
+    pub fn advance(&mut self, frame_time: f64) -> u32 {
+
+        if self.paused {
+            let ticks = self.pending_steps.min(self.max_substeps);
+            self.pending_steps -= ticks;
+            return ticks;
+        }
+
+        self.accumulator += frame_time * self.time_scale;
+
+        let mut ticks = (self.accumulator / self.time_step) as u32;
+        if ticks > self.max_substeps {
+            ticks = self.max_substeps;
+            self.accumulator -= ticks as f64 * self.time_step;
+            // Drop the backlog to avoid the spiral of death.
+            self.accumulator %= self.time_step;
+        }
+        else {
+            self.accumulator -= ticks as f64 * self.time_step;
+        }
+
+        ticks
+    }
+
+    /// The interpolation factor [0, 1) between the previous and the current simulation state.
+    pub fn alpha(&self) -> f64 {
+        if self.paused { 0.0 } else { (self.accumulator / self.time_step).clamp(0.0, 1.0) }
+    }
+
+    /// The simulated time of one tick in seconds.
+    pub fn time_step(&self) -> f64 {
+        self.time_step
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pause or resume the clock. The accumulated time is dropped.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.accumulator = 0.0;
+        self.pending_steps = 0;
+    }
+
+    /// Run a single tick on the next advance. Only works while paused.
+    pub fn single_step(&mut self) {
+        if self.paused {
+            self.pending_steps += 1;
+        }
+    }
+
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    /// Set the speed of the simulated time compared to the real time.
+    pub fn set_time_scale(&mut self, time_scale: f64) {
+        self.time_scale = time_scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+    }
+
+    /// Toggle pause, single step or change the time scale if the keys were pressed.
+    pub fn apply_keys(&mut self, keys: &FixedStepKeys, input: &InputCache) {
+
+        let pressed = |key: Option<Key>| match key {
+            Some(k) => matches!(input.key_state(&k), Some(InputState::Pressed(_))),
+            None => false,
+        };
+
+        if pressed(keys.pause) {
+            self.set_paused(!self.paused);
+            log::info!("Simulation {}.", if self.paused { "paused" } else { "resumed" });
+        }
+        if pressed(keys.step) {
+            self.single_step();
+        }
+        if pressed(keys.faster) {
+            self.set_time_scale(self.time_scale * 2.0);
+            log::info!("Time scale {}.", self.time_scale);
+        }
+        if pressed(keys.slower) {
+            self.set_time_scale(self.time_scale * 0.5);
+            log::info!("Time scale {}.", self.time_scale);
+        }
+    }
+}
+
+/// The key bindings of FixedStepClock. None disables the binding.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedStepKeys {
+    pub pause: Option<Key>,
+    pub step: Option<Key>,
+    pub faster: Option<Key>,
+    pub slower: Option<Key>,
+}
+
+impl Default for FixedStepKeys {
+    fn default() -> Self {
+        Self {
+            pause: Some(Key::P),
+            step: Some(Key::N),
+            faster: Some(Key::Period),
+            slower: Some(Key::Comma),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulates_ticks() {
+        let mut clock = FixedStepClock::new(10.0, 8);
+        assert_eq!(clock.advance(0.05), 0);
+        assert_eq!(clock.advance(0.06), 1);
+        assert!((clock.alpha() - 0.1).abs() < 1e-9);
+        assert_eq!(clock.advance(0.25), 2);
+    }
+
+    #[test]
+    fn max_substeps() {
+        let mut clock = FixedStepClock::new(10.0, 3);
+        assert_eq!(clock.advance(1.05), 3);
+        assert!(clock.alpha() < 1.0);
+        assert_eq!(clock.advance(0.0), 0);
+    }
+
+    #[test]
+    fn pause_and_single_step() {
+        let mut clock = FixedStepClock::new(10.0, 8);
+        clock.set_paused(true);
+        assert_eq!(clock.advance(1.0), 0);
+        clock.single_step();
+        assert_eq!(clock.advance(1.0), 1);
+        assert_eq!(clock.advance(1.0), 0);
+    }
+
+    #[test]
+    fn time_scale() {
+        let mut clock = FixedStepClock::new(10.0, 8);
+        clock.set_time_scale(2.0);
+        assert_eq!(clock.advance(0.1), 2);
+        clock.set_time_scale(1000.0);
+        assert_eq!(clock.time_scale(), 64.0);
+    }
+}
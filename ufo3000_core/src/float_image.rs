@@ -0,0 +1,496 @@
+use crate::image::{ImageError, f16_to_f32};
+
+/// A float image or volume. The texels are stored row by row, slice by slice with channels
+/// interleaved.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FloatImage {
+    pub width: u32,
+    pub height: u32,
+    /// 1 for 2d images.
+    pub depth: u32,
+    /// 1 to 4 components per texel.
+    pub channels: u32,
+    pub data: Vec<f32>,
+}
+
+/// The product of the dimensions or None if it overflows.
+fn checked_count(dimensions: &[u32]) -> Option<usize> {
+    dimensions.iter().try_fold(1usize, |count, &d| count.checked_mul(d as usize))
+}
+
+impl FloatImage {
+
+    pub fn new(width: u32, height: u32, depth: u32, channels: u32, data: Vec<f32>) -> Self {
+        assert!((1..=4).contains(&channels), "FloatImage: channels must be 1..4.");
+        assert!(checked_count(&[width, height, depth, channels]) == Some(data.len()), "FloatImage: data doesn't match the size.");
+        Self { width, height, depth, channels, data }
+    }
+
+    /// The texels as rgba. One channel is expanded to gray, missing components are 0 and a
+    /// missing alpha is 1.
+    pub fn rgba_texels(&self) -> Vec<[f32; 4]> {
+        self.data.chunks_exact(self.channels as usize).map(|c| match c.len() {
+            1 => [c[0], c[0], c[0], 1.0],
+            2 => [c[0], c[1], 0.0, 1.0],
+            3 => [c[0], c[1], c[2], 1.0],
+            _ => [c[0], c[1], c[2], c[3]],
+        }).collect()
+    }
+
+    /// Create an image from texel data of a float format (R32Float, Rg32Float, Rgba32Float,
+    /// R16Float, Rg16Float or Rgba16Float).
+    pub fn from_texel_data(width: u32, height: u32, depth: u32, format: wgpu::TextureFormat, data: &[u8]) -> Result<Self, ImageError> {
+
+        use wgpu::TextureFormat as F;
+
+        let (channels, half) = match format {
+            F::R32Float => (1, false),
+            F::Rg32Float => (2, false),
+            F::Rgba32Float => (4, false),
+            F::R16Float => (1, true),
+            F::Rg16Float => (2, true),
+            F::Rgba16Float => (4, true),
+            f => return Err(ImageError::Unsupported(format!("the texture format {:?}", f))),
+        };
+
+        let data = if half {
+            data.chunks_exact(2).map(|c| f16_to_f32(u16::from_le_bytes([c[0], c[1]]))).collect()
+        } else {
+            data.chunks_exact(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect()
+        };
+
+        Ok(Self::new(width, height, depth, channels, data))
+    }
+}
+
+/// Split the header lines of a Radiance file. Returns the lines and the rest of the data.
+fn hdr_header(bytes: &[u8]) -> Result<(Vec<String>, &[u8]), ImageError> {
+    let mut lines = Vec::new();
+    let mut rest = bytes;
+    loop {
+        let end = rest.iter().position(|&b| b == b'\n')
+            .ok_or_else(|| ImageError::Invalid("the hdr header is truncated".into()))?;
+        let line = String::from_utf8_lossy(&rest[..end]).trim_end().to_string();
+        rest = &rest[end + 1..];
+        // The resolution line ends the header.
+        if line.starts_with("-Y") || line.starts_with("+Y") {
+            lines.push(line);
+            return Ok((lines, rest));
+        }
+        lines.push(line);
+    }
+}
+
+fn rgbe_to_rgb(rgbe: &[u8]) -> [f32; 3] {
+    if rgbe[3] == 0 {
+        return [0.0; 3];
+    }
+    let f = 2f32.powi(rgbe[3] as i32 - (128 + 8));
+    [(rgbe[0] as f32 + 0.5) * f, (rgbe[1] as f32 + 0.5) * f, (rgbe[2] as f32 + 0.5) * f]
+}
+
+fn rgb_to_rgbe(rgb: &[f32]) -> [u8; 4] {
+    // Negative and NaN values are 0. Larger values than rgbe can hold (a mantissa of 255/256
+    // with the exponent 127) are clamped, including infinity.
+    let max = 255.0 / 256.0 * 2f32.powi(127);
+    let rgb = [0, 1, 2].map(|i| if rgb[i] > 0.0 { rgb[i].min(max) } else { 0.0 });
+    let v = rgb[0].max(rgb[1]).max(rgb[2]);
+    if v < 1e-32 {
+        return [0; 4];
+    }
+    // v = m * 2^e with m in [0.5, 1).
+    let mut e = v.log2().floor() as i32 + 1;
+    let mut m = v / 2f32.powi(e);
+    if m >= 1.0 { e += 1; m /= 2.0; }
+    if m < 0.5 { e -= 1; m *= 2.0; }
+    let scale = m * 256.0 / v;
+    [(rgb[0] * scale) as u8, (rgb[1] * scale) as u8, (rgb[2] * scale) as u8, (e + 128) as u8]
+}
+
+/// Decode a Radiance .hdr (rgbe) image. Returns a 3 channel image with the top row first.
+pub fn decode_hdr(bytes: &[u8]) -> Result<FloatImage, ImageError> {
+
+    let (header, mut rest) = hdr_header(bytes)?;
+
+    if !header[0].starts_with("#?") {
+        return Err(ImageError::Invalid("not a Radiance file".into()));
+    }
+    if header.iter().any(|l| l.starts_with("FORMAT=") && l != "FORMAT=32-bit_rle_rgbe") {
+        return Err(ImageError::Unsupported("only the 32-bit_rle_rgbe format is supported".into()));
+    }
+
+    let resolution: Vec<&str> = header.last().unwrap().split_whitespace().collect();
+    let (flip, height, width) = match resolution.as_slice() {
+        [y, h, "+X", w] => (*y == "+Y", h.parse::<u32>(), w.parse::<u32>()),
+        _ => return Err(ImageError::Unsupported(format!("the orientation {:?}", resolution))),
+    };
+    let (height, width) = match (height, width) {
+        (Ok(h), Ok(w)) => (h, w),
+        _ => return Err(ImageError::Invalid("invalid hdr resolution".into())),
+    };
+    if width == 0 || height == 0 {
+        return Err(ImageError::Invalid("empty hdr image".into()));
+    }
+
+    let truncated = || ImageError::Invalid("the hdr data is truncated".into());
+    let w = width as usize;
+    checked_count(&[width, height, 4]).ok_or_else(|| ImageError::Invalid("the hdr image is too large".into()))?;
+    // Every scanline takes at least 4 bytes. The output grows row by row, so the header alone
+    // can't make it allocate more than the data describes.
+    if checked_count(&[height, 4]).map_or(true, |min_size| rest.len() < min_size) {
+        return Err(truncated());
+    }
+
+    let mut data: Vec<f32> = Vec::new();
+    let mut row: Vec<u8> = Vec::new();
+
+    for _ in 0..height {
+
+        row.clear();
+        let new_rle = (8..=0x7fff).contains(&w) && rest.len() >= 4 &&
+                      rest[0] == 2 && rest[1] == 2 && ((rest[2] as usize) << 8 | rest[3] as usize) == w;
+
+        if new_rle {
+            row.resize(w * 4, 0);
+            rest = &rest[4..];
+            // The channels are stored one after another.
+            for channel in 0..4 {
+                let mut x = 0;
+                while x < w {
+                    let count = *rest.first().ok_or_else(truncated)? as usize;
+                    if count > 128 {
+                        let count = count - 128;
+                        let value = *rest.get(1).ok_or_else(truncated)?;
+                        if x + count > w { return Err(ImageError::Invalid("hdr run overflows the scanline".into())); }
+                        row[x * 4..(x + count) * 4].iter_mut().skip(channel).step_by(4).for_each(|v| *v = value);
+                        rest = &rest[2..];
+                        x += count;
+                    }
+                    else {
+                        if count == 0 || x + count > w { return Err(ImageError::Invalid("invalid hdr scanline".into())); }
+                        let values = rest.get(1..1 + count).ok_or_else(truncated)?;
+                        for (i, &v) in values.iter().enumerate() { row[(x + i) * 4 + channel] = v; }
+                        rest = &rest[1 + count..];
+                        x += count;
+                    }
+                }
+            }
+        }
+        else {
+            // Flat pixels with the old run-length encoding (1, 1, 1, count).
+            let mut x = 0;
+            let mut shift = 0;
+            while x < w {
+                let pixel = rest.get(..4).ok_or_else(truncated)?;
+                rest = &rest[4..];
+                if pixel[0] == 1 && pixel[1] == 1 && pixel[2] == 1 && x > 0 {
+                    // Consecutive markers add 8 bits to the count, a 32-bit count is enough.
+                    if shift > 24 { return Err(ImageError::Invalid("too many hdr run markers".into())); }
+                    let count = (pixel[3] as usize) << shift;
+                    if x + count > w { return Err(ImageError::Invalid("hdr run overflows the scanline".into())); }
+                    let previous: [u8; 4] = row[(x - 1) * 4..x * 4].try_into().unwrap();
+                    for _ in 0..count { row.extend_from_slice(&previous); }
+                    x += count;
+                    shift += 8;
+                }
+                else {
+                    row.extend_from_slice(pixel);
+                    x += 1;
+                    shift = 0;
+                }
+            }
+        }
+
+        data.extend(row.chunks_exact(4).flat_map(rgbe_to_rgb));
+    }
+
+    if flip {
+        let rows: Vec<&[f32]> = data.chunks_exact(w * 3).rev().collect();
+        data = rows.concat();
+    }
+
+    Ok(FloatImage::new(width, height, 1, 3, data))
+}
+
+/// Encode a 2d image as a run-length encoded Radiance .hdr file. Alpha is dropped.
+pub fn encode_hdr(image: &FloatImage) -> Vec<u8> {
+
+    assert!(image.depth == 1, "encode_hdr: only 2d images can be saved.");
+
+    let w = image.width as usize;
+    let mut bytes = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", image.height, image.width).into_bytes();
+
+    let rgbe: Vec<[u8; 4]> = image.rgba_texels().iter().map(|t| rgb_to_rgbe(&t[..3])).collect();
+
+    for row in rgbe.chunks_exact(w.max(1)) {
+        if (8..=0x7fff).contains(&w) {
+            bytes.extend_from_slice(&[2, 2, (w >> 8) as u8, (w & 0xff) as u8]);
+            // Literal runs of at most 128 values per channel.
+            for channel in 0..4 {
+                let values: Vec<u8> = row.iter().map(|p| p[channel]).collect();
+                for chunk in values.chunks(128) {
+                    bytes.push(chunk.len() as u8);
+                    bytes.extend_from_slice(chunk);
+                }
+            }
+        }
+        else {
+            for p in row.iter() { bytes.extend_from_slice(p); }
+        }
+    }
+
+    bytes
+}
+
+/// The magic bytes of the raw volume format.
+const VOLUME_MAGIC: &[u8; 4] = b"UFOV";
+
+/// The texture formats of raw volumes and their ids in the header.
+const VOLUME_FORMATS: [(u32, wgpu::TextureFormat); 6] = [
+    (0, wgpu::TextureFormat::R8Unorm),
+    (1, wgpu::TextureFormat::R16Float),
+    (2, wgpu::TextureFormat::R32Float),
+    (3, wgpu::TextureFormat::Rgba8Unorm),
+    (4, wgpu::TextureFormat::Rgba16Float),
+    (5, wgpu::TextureFormat::Rgba32Float),
+];
+
+/// A raw volume: a 24 byte header ("UFOV", width, height, depth, format id and the payload
+/// size as little endian u32s) followed by the texel data of the format, x fastest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawVolume {
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub format: wgpu::TextureFormat,
+    pub data: Vec<u8>,
+}
+
+impl RawVolume {
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, ImageError> {
+
+        if bytes.len() < 24 || &bytes[..4] != VOLUME_MAGIC {
+            return Err(ImageError::Invalid("not a raw volume".into()));
+        }
+        let field = |i: usize| u32::from_le_bytes([bytes[4 * i], bytes[4 * i + 1], bytes[4 * i + 2], bytes[4 * i + 3]]);
+        let (width, height, depth, format_id, size) = (field(1), field(2), field(3), field(4), field(5) as usize);
+
+        let format = VOLUME_FORMATS.iter().find(|(id, _)| *id == format_id).map(|(_, f)| *f)
+            .ok_or_else(|| ImageError::Unsupported(format!("the volume format id {}", format_id)))?;
+
+        let expected = checked_count(&[width, height, depth, format.block_size(None).unwrap()])
+            .ok_or_else(|| ImageError::Invalid("the volume is too large".into()))?;
+        if size != expected || bytes.len() - 24 < size {
+            return Err(ImageError::Invalid(format!("the volume payload is {} bytes, expected {}", bytes.len() - 24, expected)));
+        }
+
+        Ok(Self { width, height, depth, format, data: bytes[24..24 + size].to_vec() })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let format_id = VOLUME_FORMATS.iter().find(|(_, f)| *f == self.format).map(|(id, _)| *id)
+            .unwrap_or_else(|| panic!("RawVolume: the format {:?} isn't supported.", self.format));
+        let mut bytes = VOLUME_MAGIC.to_vec();
+        for v in [self.width, self.height, self.depth, format_id, self.data.len() as u32] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+}
+
+/// Decode a NumPy .npy array (versions 1 to 3, C order) of f2, f4, f8, u1, u2, u4, i1, i2 or
+/// i4 values. The shape is interpreted as (height, width), (height, width, channels) if the
+/// last dimension is 1..4, (depth, height, width) or (depth, height, width, channels).
+pub fn decode_npy(bytes: &[u8]) -> Result<FloatImage, ImageError> {
+
+    if bytes.len() < 10 || &bytes[..6] != b"\x93NUMPY" {
+        return Err(ImageError::Invalid("not a npy file".into()));
+    }
+    let (header_len, header_start) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        2 | 3 if bytes.len() >= 12 => (u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize, 12),
+        v => return Err(ImageError::Unsupported(format!("npy version {}", v))),
+    };
+    let header = bytes.get(header_start..header_start + header_len)
+        .ok_or_else(|| ImageError::Invalid("the npy header is truncated".into()))?;
+    let header = String::from_utf8_lossy(header);
+    let payload = &bytes[header_start + header_len..];
+
+    let value_of = |key: &str| -> Option<String> {
+        let start = header.find(&format!("'{}':", key))? + key.len() + 3;
+        let rest = header[start..].trim_start();
+        let end = if rest.starts_with('(') { rest.find(')')? + 1 } else { rest.find(',')? };
+        Some(rest[..end].trim().to_string())
+    };
+
+    let descr = value_of("descr").ok_or_else(|| ImageError::Invalid("npy descr is missing".into()))?;
+    if value_of("fortran_order").as_deref() != Some("False") {
+        return Err(ImageError::Unsupported("Fortran ordered npy arrays".into()));
+    }
+    let shape: Vec<u32> = value_of("shape").ok_or_else(|| ImageError::Invalid("npy shape is missing".into()))?
+        .trim_matches(|c| c == '(' || c == ')')
+        .split(',')
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| s.trim().parse::<u32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| ImageError::Invalid("invalid npy shape".into()))?;
+
+    let descr = descr.trim_matches('\'');
+    let little = !descr.starts_with('>');
+    let dtype = descr.get(1..).ok_or_else(|| ImageError::Invalid(format!("invalid npy descr {:?}", descr)))?;
+    let (size, convert): (usize, fn(&[u8]) -> f32) = match dtype {
+        "f2" => (2, |b| f16_to_f32(u16::from_le_bytes([b[0], b[1]]))),
+        "f4" => (4, |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        "f8" => (8, |b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32),
+        "u1" => (1, |b| b[0] as f32),
+        "i1" => (1, |b| b[0] as i8 as f32),
+        "u2" => (2, |b| u16::from_le_bytes([b[0], b[1]]) as f32),
+        "i2" => (2, |b| i16::from_le_bytes([b[0], b[1]]) as f32),
+        "u4" => (4, |b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32),
+        "i4" => (4, |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32),
+        d => return Err(ImageError::Unsupported(format!("the npy dtype {}", d))),
+    };
+
+    let (depth, height, width, channels) = match shape.as_slice() {
+        [h, w] => (1, *h, *w, 1),
+        [h, w, c] if (1..=4).contains(c) => (1, *h, *w, *c),
+        [d, h, w] => (*d, *h, *w, 1),
+        [d, h, w, c] if (1..=4).contains(c) => (*d, *h, *w, *c),
+        s => return Err(ImageError::Unsupported(format!("the npy shape {:?}", s))),
+    };
+
+    let bytes_size = checked_count(&[depth, height, width, channels, size as u32])
+        .ok_or_else(|| ImageError::Invalid("the npy array is too large".into()))?;
+    if payload.len() < bytes_size {
+        return Err(ImageError::Invalid("the npy data is truncated".into()));
+    }
+
+    let data = payload[..bytes_size].chunks_exact(size).map(|b| {
+        if little || size == 1 { convert(b) } else { convert(&b.iter().rev().copied().collect::<Vec<u8>>()) }
+    }).collect();
+
+    Ok(FloatImage::new(width, height, depth, channels, data))
+}
+
+/// Encode the image as a little endian f4 .npy array with the shape (height, width, channels)
+/// or (depth, height, width, channels).
+pub fn encode_npy(image: &FloatImage) -> Vec<u8> {
+
+    let shape = if image.depth == 1 {
+        format!("({}, {}, {})", image.height, image.width, image.channels)
+    } else {
+        format!("({}, {}, {}, {})", image.depth, image.height, image.width, image.channels)
+    };
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': {}, }}", shape);
+
+    // The header (with the 10 byte preamble and the newline) is padded to 64 bytes.
+    let padding = 64 - (10 + header.len() + 1) % 64;
+    header.push_str(&" ".repeat(padding % 64));
+    header.push('\n');
+
+    let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    for v in image.data.iter() {
+        bytes.extend_from_slice(&v.to_le_bytes());
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_image(width: u32, height: u32) -> FloatImage {
+        let data = (0..width * height * 3).map(|i| (i % 7) as f32 * 0.75 + 0.01).collect();
+        FloatImage::new(width, height, 1, 3, data)
+    }
+
+    #[test]
+    fn hdr_roundtrip() {
+        // Run-length encoded and flat scanlines.
+        for width in [5, 20] {
+            let image = test_image(width, 3);
+            let decoded = decode_hdr(&encode_hdr(&image)).unwrap();
+            assert_eq!((decoded.width, decoded.height, decoded.channels), (width, 3, 3));
+            for (a, b) in image.data.iter().zip(decoded.data.iter()) {
+                assert!((a - b).abs() <= 0.04, "{} != {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn rgbe_range() {
+        let largest = rgbe_to_rgb(&[255, 255, 255, 255])[0];
+        for v in [f32::INFINITY, f32::MAX, 1e38] {
+            let rgbe = rgb_to_rgbe(&[v, 1.0, 0.0]);
+            assert_eq!(rgbe[3], 255);
+            assert!(rgbe_to_rgb(&rgbe)[0] <= largest);
+        }
+        assert_eq!(rgb_to_rgbe(&[f32::NAN, f32::NEG_INFINITY, -1.0]), [0; 4]);
+        assert_eq!(rgb_to_rgbe(&[f32::NAN, 1.0, 0.0])[0], 0);
+    }
+
+    #[test]
+    fn npy_roundtrip() {
+        let image = FloatImage::new(2, 3, 4, 2, (0..48).map(|i| i as f32 - 10.5).collect());
+        let bytes = encode_npy(&image);
+        assert_eq!((bytes.len() - 48 * 4) % 64, 0);
+        assert_eq!(decode_npy(&bytes).unwrap(), image);
+    }
+
+    #[test]
+    fn volume_roundtrip() {
+        let volume = RawVolume { width: 2, height: 2, depth: 2, format: wgpu::TextureFormat::R32Float, data: vec![7; 32] };
+        assert_eq!(RawVolume::decode(&volume.encode()).unwrap(), volume);
+        assert!(RawVolume::decode(&volume.encode()[..40]).is_err());
+    }
+
+    #[test]
+    fn malformed_input() {
+        let volume = RawVolume { width: 2, height: 2, depth: 1, format: wgpu::TextureFormat::Rgba8Unorm, data: vec![1; 16] };
+        let valid = [encode_hdr(&test_image(5, 2)), encode_hdr(&test_image(20, 2)), encode_npy(&test_image(3, 2)), volume.encode()];
+
+        // Every truncation and some garbage must give an error, not a panic.
+        let mut seed = 12345u32;
+        let garbage: Vec<u8> = (0..256).map(|_| { seed = seed.wrapping_mul(1103515245).wrapping_add(12345); (seed >> 16) as u8 }).collect();
+        for bytes in valid.iter() {
+            for end in 0..bytes.len() {
+                let _ = (decode_hdr(&bytes[..end]), decode_npy(&bytes[..end]), RawVolume::decode(&bytes[..end]));
+            }
+            let mut corrupted = bytes.clone();
+            let len = corrupted.len();
+            corrupted[len / 2..].copy_from_slice(&garbage.iter().cycle().take(len - len / 2).copied().collect::<Vec<u8>>());
+            let _ = (decode_hdr(&corrupted), decode_npy(&corrupted), RawVolume::decode(&corrupted));
+        }
+        assert!(decode_hdr(&garbage).is_err());
+        assert!(decode_npy(&garbage).is_err());
+        assert!(RawVolume::decode(&garbage).is_err());
+
+        // An empty descr and a shape that overflows.
+        let npy = |header: &str| {
+            let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+            bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(header.as_bytes());
+            bytes
+        };
+        assert!(decode_npy(&npy("{'descr': '', 'fortran_order': False, 'shape': (2, 2), }\n")).is_err());
+        assert!(decode_npy(&npy("{'descr': '<f8', 'fortran_order': False, 'shape': (4294967295, 4294967295, 4294967295, 4), }\n")).is_err());
+
+        // A resolution that the data can't hold.
+        assert!(decode_hdr(b"#?RADIANCE\n\n-Y 1073741824 +X 2147483648\n\x02\x02").is_err());
+
+        // Consecutive old-style run markers.
+        let mut hdr = b"#?RADIANCE\n\n-Y 1 +X 4\n".to_vec();
+        hdr.extend_from_slice(&[10, 10, 10, 128]);
+        for _ in 0..10 { hdr.extend_from_slice(&[1, 1, 1, 0]); }
+        assert!(decode_hdr(&hdr).is_err());
+
+        // A volume whose size overflows.
+        let mut bytes = VOLUME_MAGIC.to_vec();
+        for v in [u32::MAX, u32::MAX, u32::MAX, 5, 0] { bytes.extend_from_slice(&v.to_le_bytes()); }
+        assert!(RawVolume::decode(&bytes).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+/// The phases of a frame that are timed separately.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FramePhase {
+    Input,
+    Update,
+    Render,
+}
+
+/// The timings of a single frame in milliseconds.
+#[derive(Clone, Copy, Debug, Default)]
+struct FrameSample {
+    frame_time: f64,
+    input: f64,
+    update: f64,
+    render: f64,
+}
+
+/// Collects the frame times and the cpu times of the application phases over a rolling window
+/// of frames. The loops record the timings and pass them to Application::frame_finished.
+#[derive(Clone, Debug)]
+pub struct FrameStats {
+    samples: VecDeque<FrameSample>,
+    window: usize,
+    current: FrameSample,
+    report_interval: Option<Duration>,
+    since_report: Duration,
+    total_frames: u64,
+}
+
+/// A summary of the frames in the window. The times are in milliseconds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameSummary {
+    pub frames: usize,
+    pub fps: f64,
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+    pub p95: f64,
+    pub p99: f64,
+    /// The average cpu time of Application::input.
+    pub input: f64,
+    /// The average cpu time of Application::update (or fixed_update).
+    pub update: f64,
+    /// The average cpu time of Application::render.
+    pub render: f64,
+}
+
+impl fmt::Display for FrameSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{:.1} fps | frame min {:.2} avg {:.2} max {:.2} p95 {:.2} p99 {:.2} ms | input {:.2} update {:.2} render {:.2} ms ({} frames)",
+               self.fps, self.min, self.avg, self.max, self.p95, self.p99, self.input, self.update, self.render, self.frames)
+    }
+}
+
+impl FrameStats {
+
+    /// Create FrameStats that keeps the timings of the last window frames.
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "FrameStats window must be > 0.");
+        Self {
+            samples: VecDeque::with_capacity(window),
+            window,
+            current: FrameSample::default(),
+            report_interval: None,
+            since_report: Duration::ZERO,
+            total_frames: 0,
+        }
+    }
+
+    /// Change the size of the rolling window. The oldest samples are dropped if needed.
+    pub fn set_window(&mut self, window: usize) {
+        assert!(window > 0, "FrameStats window must be > 0.");
+        self.window = window;
+        while self.samples.len() > window {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Log a summary every interval (measured in frame time). None disables the reports.
+    pub fn set_report_interval(&mut self, report_interval: Option<Duration>) {
+        self.report_interval = report_interval;
+        self.since_report = Duration::ZERO;
+    }
+
+    /// Discard the phase times recorded since the last frame. The loops call this at the start
+    /// of every iteration, so a frame only counts the phases of the iteration that rendered it.
+    pub fn reset_phases(&mut self) {
+        self.current = FrameSample::default();
+    }
+
+    /// Add the cpu time of a phase to the current frame.
+    pub fn record_phase(&mut self, phase: FramePhase, duration: Duration) {
+        let ms = duration.as_secs_f64() * 1000.0;
+        match phase {
+            FramePhase::Input => self.current.input += ms,
+            FramePhase::Update => self.current.update += ms,
+            FramePhase::Render => self.current.render += ms,
+        }
+    }
+
+    /// Finish the current frame. frame_time is the time since the previous frame.
+    pub fn end_frame(&mut self, frame_time: Duration) {
+
+        self.current.frame_time = frame_time.as_secs_f64() * 1000.0;
+
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(std::mem::take(&mut self.current));
+        self.total_frames += 1;
+
+        if let Some(interval) = self.report_interval {
+            self.since_report += frame_time;
+            if self.since_report >= interval {
+                self.since_report = Duration::ZERO;
+                crate::logger::log_frame_summary(&self.summary());
+            }
+        }
+    }
+
+    /// The number of frames since the start.
+    pub fn total_frames(&self) -> u64 {
+        self.total_frames
+    }
+
+    /// The frame time of the latest frame in milliseconds.
+    pub fn last_frame_time(&self) -> Option<f64> {
+        self.samples.back().map(|s| s.frame_time)
+    }
+
+    /// Summarize the frames of the window.
+    pub fn summary(&self) -> FrameSummary {
+
+        let frames = self.samples.len();
+        if frames == 0 {
+            return FrameSummary::default();
+        }
+
+        let mut frame_times: Vec<f64> = self.samples.iter().map(|s| s.frame_time).collect();
+        frame_times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let n = frames as f64;
+        let avg = frame_times.iter().sum::<f64>() / n;
+
+        FrameSummary {
+            frames,
+            fps: if avg > 0.0 { 1000.0 / avg } else { 0.0 },
+            min: frame_times[0],
+            avg,
+            max: frame_times[frames - 1],
+            p95: percentile(&frame_times, 0.95),
+            p99: percentile(&frame_times, 0.99),
+            input: self.samples.iter().map(|s| s.input).sum::<f64>() / n,
+            update: self.samples.iter().map(|s| s.update).sum::<f64>() / n,
+            render: self.samples.iter().map(|s| s.render).sum::<f64>() / n,
+        }
+    }
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary() {
+        let mut stats = FrameStats::new(100);
+        for i in 1..=100 {
+            stats.record_phase(FramePhase::Update, Duration::from_millis(2));
+            stats.end_frame(Duration::from_millis(i));
+        }
+        let summary = stats.summary();
+        assert_eq!(summary.frames, 100);
+        assert_eq!(summary.min, 1.0);
+        assert_eq!(summary.max, 100.0);
+        assert_eq!(summary.p95, 95.0);
+        assert_eq!(summary.p99, 99.0);
+        assert!((summary.avg - 50.5).abs() < 1e-9);
+        assert!((summary.update - 2.0).abs() < 1e-9);
+        assert_eq!(summary.render, 0.0);
+    }
+
+    #[test]
+    fn reset_phases() {
+        let mut stats = FrameStats::new(10);
+        // Iterations that don't render must not add up into the next frame.
+        for _ in 0..5 {
+            stats.reset_phases();
+            stats.record_phase(FramePhase::Input, Duration::from_millis(1));
+        }
+        stats.record_phase(FramePhase::Render, Duration::from_millis(3));
+        stats.end_frame(Duration::from_millis(16));
+        let summary = stats.summary();
+        assert!((summary.input - 1.0).abs() < 1e-9);
+        assert!((summary.render - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rolling_window() {
+        let mut stats = FrameStats::new(2);
+        stats.end_frame(Duration::from_millis(10));
+        stats.end_frame(Duration::from_millis(20));
+        stats.end_frame(Duration::from_millis(30));
+        assert_eq!(stats.summary().min, 20.0);
+        assert_eq!(stats.total_frames(), 3);
+        assert_eq!(stats.last_frame_time(), Some(30.0));
+    }
+}
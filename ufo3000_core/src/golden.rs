@@ -0,0 +1,215 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use crate::template::{Application, Loop, OffscreenLoop, SetupError, WGPUFeatures, setup_offscreen};
+use crate::image::{read_png_rgba8, write_png_rgba8};
+
+/// Options for comparing a rendered image against a golden png.
+#[derive(Clone, Copy, Debug)]
+pub struct GoldenOptions {
+    /// The number of rendered frames. The last frame is compared.
+    pub frames: u32,
+    /// The maximum allowed difference per color channel.
+    pub tolerance: u8,
+    /// The number of pixels that may exceed the tolerance.
+    pub max_differing_pixels: usize,
+    /// Use the software/fallback adapter.
+    pub force_fallback_adapter: bool,
+}
+
+impl Default for GoldenOptions {
+    fn default() -> Self {
+        Self {
+            frames: 1,
+            tolerance: 2,
+            max_differing_pixels: 0,
+            force_fallback_adapter: false,
+        }
+    }
+}
+
+/// The result of an image comparison.
+pub struct ImageComparison {
+    /// The number of pixels that exceed the tolerance.
+    pub differing_pixels: usize,
+    /// The largest channel difference.
+    pub max_difference: u8,
+    /// An rgba8 heatmap of the differences.
+    pub heatmap: Vec<u8>,
+}
+
+/// The reasons why a golden image test fails.
+#[derive(Debug)]
+pub enum GoldenError {
+    /// wgpu-rs couldn't be initialized (for example there is no adapter).
+    Setup(SetupError),
+    /// The golden file doesn't exist. The rendered image is written to actual.
+    Missing { golden: PathBuf, actual: PathBuf },
+    /// The golden image and the rendered image have different sizes.
+    SizeMismatch { expected: (u32, u32), actual: (u32, u32) },
+    /// Too many pixels exceed the tolerance. The rendered image and the heatmap are written
+    /// next to the golden file.
+    Mismatch { differing_pixels: usize, max_difference: u8, actual: PathBuf, diff: PathBuf },
+    Decode(png::DecodingError),
+    Encode(png::EncodingError),
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GoldenError::Setup(e) => write!(f, "Setup failed: {}", e),
+            GoldenError::Missing { golden, actual } =>
+                write!(f, "Golden image {:?} doesn't exist. The rendered image was written to {:?}. Set UFO3000_UPDATE_GOLDEN=1 to accept it.", golden, actual),
+            GoldenError::SizeMismatch { expected, actual } =>
+                write!(f, "Image size mismatch: expected {:?}, actual {:?}", expected, actual),
+            GoldenError::Mismatch { differing_pixels, max_difference, actual, diff } =>
+                write!(f, "{} pixels differ (max difference {}). See {:?} and {:?}.", differing_pixels, max_difference, actual, diff),
+            GoldenError::Decode(e) => write!(f, "Couldn't read the golden image: {}", e),
+            GoldenError::Encode(e) => write!(f, "Couldn't write the image: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+impl From<png::DecodingError> for GoldenError {
+    fn from(e: png::DecodingError) -> Self {
+        GoldenError::Decode(e)
+    }
+}
+
+impl From<png::EncodingError> for GoldenError {
+    fn from(e: png::EncodingError) -> Self {
+        GoldenError::Encode(e)
+    }
+}
+
+/// Compare two rgba8 images of the same size.
+pub fn compare_images(actual: &[u8], expected: &[u8], tolerance: u8) -> ImageComparison {
+
+    assert!(actual.len() == expected.len(), "compare_images: the images must have the same size.");
+
+    let mut differing_pixels = 0;
+    let mut max_difference = 0;
+    let mut heatmap: Vec<u8> = Vec::with_capacity(actual.len());
+
+    for (a, e) in actual.chunks_exact(4).zip(expected.chunks_exact(4)) {
+        let difference = a.iter().zip(e.iter()).map(|(x, y)| x.abs_diff(*y)).max().unwrap();
+        max_difference = max_difference.max(difference);
+
+        if difference > tolerance {
+            differing_pixels += 1;
+            // From red to yellow.
+            heatmap.extend_from_slice(&[255, difference, 0, 255]);
+        }
+        else {
+            // A dimmed gray version of the expected image.
+            let gray = ((e[0] as u32 + e[1] as u32 + e[2] as u32) / 12) as u8;
+            heatmap.extend_from_slice(&[gray, gray, gray, 255]);
+        }
+    }
+
+    ImageComparison {
+        differing_pixels,
+        max_difference,
+        heatmap,
+    }
+}
+
+/// Render application A offscreen and compare the last frame against the golden png. If the
+/// golden file is missing or the UFO3000_UPDATE_GOLDEN environment variable is set to 1, true,
+/// yes or on, the rendered image is written to <golden>.actual.png (or to the golden file when updating).
+pub fn check_golden<A: Application, F: WGPUFeatures>(golden: &Path, width: u32, height: u32, options: &GoldenOptions) -> Result<(), GoldenError> {
+
+    let mut configuration = pollster::block_on(setup_offscreen::<F>(width, height, options.force_fallback_adapter))
+        .map_err(GoldenError::Setup)?;
+
+    let mut application = A::init(&configuration);
+    let offscreen_loop = OffscreenLoop::init()
+        .with_frame_count(options.frames)
+        .with_output_dir(None::<&Path>);
+    let pixels = offscreen_loop.render_frames(&mut application, &mut configuration);
+
+    let actual_path = sibling_path(golden, "actual");
+
+    if std::env::var("UFO3000_UPDATE_GOLDEN").map_or(false, |v| is_truthy(&v)) {
+        write_png_rgba8(golden, width, height, &pixels)?;
+        log::info!("Updated golden image {:?}.", golden);
+        return Ok(());
+    }
+
+    if !golden.exists() {
+        write_png_rgba8(&actual_path, width, height, &pixels)?;
+        return Err(GoldenError::Missing { golden: golden.to_path_buf(), actual: actual_path });
+    }
+
+    let (expected_width, expected_height, expected) = read_png_rgba8(golden)?;
+    if (expected_width, expected_height) != (width, height) {
+        return Err(GoldenError::SizeMismatch { expected: (expected_width, expected_height), actual: (width, height) });
+    }
+
+    let comparison = compare_images(&pixels, &expected, options.tolerance);
+    if comparison.differing_pixels > options.max_differing_pixels {
+        let diff_path = sibling_path(golden, "diff");
+        write_png_rgba8(&actual_path, width, height, &pixels)?;
+        write_png_rgba8(&diff_path, width, height, &comparison.heatmap)?;
+        return Err(GoldenError::Mismatch {
+            differing_pixels: comparison.differing_pixels,
+            max_difference: comparison.max_difference,
+            actual: actual_path,
+            diff: diff_path,
+        });
+    }
+
+    Ok(())
+}
+
+/// True for 1, true, yes and on (case-insensitive). Anything else, including 0 and an empty
+/// value, is false.
+fn is_truthy(value: &str) -> bool {
+    matches!(value.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on")
+}
+
+/// foo/bar.png -> foo/bar.<suffix>.png
+fn sibling_path(golden: &Path, suffix: &str) -> PathBuf {
+    let stem = golden.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    golden.with_file_name(format!("{}.{}.png", stem, suffix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_images() {
+        let image = vec![10, 20, 30, 255, 40, 50, 60, 255];
+        let comparison = compare_images(&image, &image, 0);
+        assert_eq!(comparison.differing_pixels, 0);
+        assert_eq!(comparison.max_difference, 0);
+        assert_eq!(comparison.heatmap.len(), image.len());
+    }
+
+    #[test]
+    fn tolerance() {
+        let expected = vec![10, 20, 30, 255, 40, 50, 60, 255];
+        let actual = vec![12, 20, 30, 255, 40, 50, 70, 255];
+        let comparison = compare_images(&actual, &expected, 2);
+        assert_eq!(comparison.differing_pixels, 1);
+        assert_eq!(comparison.max_difference, 10);
+        assert_eq!(&comparison.heatmap[4..8], &[255, 10, 0, 255]);
+    }
+
+    #[test]
+    fn truthy_values() {
+        assert!(is_truthy("1"));
+        assert!(is_truthy("TRUE"));
+        assert!(is_truthy(" yes"));
+        assert!(!is_truthy("0"));
+        assert!(!is_truthy("false"));
+        assert!(!is_truthy(""));
+    }
+
+    #[test]
+    fn sibling_paths() {
+        assert_eq!(sibling_path(Path::new("golden/cube.png"), "diff"), PathBuf::from("golden/cube.diff.png"));
+    }
+}
@@ -0,0 +1,311 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Bound, Range, RangeBounds};
+use bytemuck::Pod;
+use crate::buffer::{buffer_from_data, to_vec};
+use crate::misc::vec_from_bytes;
+use crate::readback::{ReadbackPool, ReadbackHandle};
+
+/// Round size up to a multiple of wgpu::COPY_BUFFER_ALIGNMENT.
+fn align_copy_size(size: wgpu::BufferAddress) -> wgpu::BufferAddress {
+    let align = wgpu::COPY_BUFFER_ALIGNMENT;
+    (size + align - 1) / align * align
+}
+
+/// The bytes of data padded with zeros to a multiple of wgpu::COPY_BUFFER_ALIGNMENT.
+fn padded_bytes<T: Pod>(data: &[T]) -> Vec<u8> {
+    let mut bytes = bytemuck::cast_slice::<T, u8>(data).to_vec();
+    bytes.resize(align_copy_size(bytes.len() as wgpu::BufferAddress) as usize, 0);
+    bytes
+}
+
+/// The capacity after reserving room for capacity elements. None if current is enough.
+fn grown_capacity(current: usize, capacity: usize) -> Option<usize> {
+    (capacity > current).then(|| capacity.next_power_of_two())
+}
+
+/// Resolve a range of elements. Unbounded ranges end at len.
+fn element_range<R: RangeBounds<usize>>(range: R, len: usize, capacity: usize) -> Range<usize> {
+    let start = match range.start_bound() {
+        Bound::Included(&s) => s,
+        Bound::Excluded(&s) => s + 1,
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&e) => e + 1,
+        Bound::Excluded(&e) => e,
+        Bound::Unbounded => len,
+    };
+    assert!(start <= end && end <= capacity, "GpuBuffer: invalid element range {}..{} (capacity {}).", start, end, capacity);
+    start..end
+}
+
+/// Resolve a range of elements for a slice, binding or readback. wgpu doesn't allow empty
+/// ranges, so they panic instead of falling back to the whole buffer.
+fn nonempty_element_range<R: RangeBounds<usize>>(range: R, len: usize, capacity: usize, caller: &str) -> Range<usize> {
+    let range = element_range(range, len, capacity);
+    assert!(!range.is_empty(), "{}: the element range {}..{} is empty.", caller, range.start, range.end);
+    range
+}
+
+/// Errors of GpuBuffer::write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpuBufferError {
+    /// The byte offset isn't a multiple of wgpu::COPY_BUFFER_ALIGNMENT.
+    UnalignedOffset(wgpu::BufferAddress),
+    /// The byte size isn't a multiple of wgpu::COPY_BUFFER_ALIGNMENT and the write ends before
+    /// the written elements, so it can't be padded.
+    UnalignedSize(wgpu::BufferAddress),
+}
+
+impl fmt::Display for GpuBufferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GpuBufferError::UnalignedOffset(offset) =>
+                write!(f, "The byte offset {} isn't a multiple of COPY_BUFFER_ALIGNMENT", offset),
+            GpuBufferError::UnalignedSize(size) =>
+                write!(f, "The byte size {} isn't a multiple of COPY_BUFFER_ALIGNMENT and can't be padded", size),
+        }
+    }
+}
+
+impl std::error::Error for GpuBufferError {}
+
+/// A wgpu::Buffer of T elements. Keeps track of the length (the number of written elements),
+/// the capacity and the usage, so offsets and sizes can be given in elements instead of bytes.
+///
+/// write needs COPY_DST, to_vec and readback need COPY_SRC and growing a non-empty buffer
+/// needs both.
+pub struct GpuBuffer<T: Pod> {
+    buffer: wgpu::Buffer,
+    len: usize,
+    capacity: usize,
+    usage: wgpu::BufferUsages,
+    label: Option<String>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> GpuBuffer<T> {
+
+    /// Create a buffer that holds the data. The length and the capacity are data.len().
+    pub fn from_data(device: &wgpu::Device, data: &[T], usage: wgpu::BufferUsages, label: wgpu::Label) -> Self {
+
+        assert!(!data.is_empty(), "GpuBuffer::from_data: data must not be empty.");
+
+        // The buffer size is padded to COPY_BUFFER_ALIGNMENT so that the whole buffer can be copied.
+        Self {
+            buffer: buffer_from_data(device, &padded_bytes(data), usage, label),
+            len: data.len(),
+            capacity: data.len(),
+            usage,
+            label: label.map(|l| l.to_owned()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Create an empty buffer with room for capacity elements.
+    pub fn with_capacity(device: &wgpu::Device, capacity: usize, usage: wgpu::BufferUsages, label: wgpu::Label) -> Self {
+        assert!(capacity > 0, "GpuBuffer: capacity must be > 0.");
+        Self {
+            buffer: Self::create_buffer(device, capacity, usage, label),
+            len: 0,
+            capacity,
+            usage,
+            label: label.map(|l| l.to_owned()),
+            _marker: PhantomData,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize, usage: wgpu::BufferUsages, label: wgpu::Label) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label,
+            size: align_copy_size((capacity * std::mem::size_of::<T>()) as wgpu::BufferAddress),
+            usage,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// The number of written elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of elements the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn usage(&self) -> wgpu::BufferUsages {
+        self.usage
+    }
+
+    /// The size of the wgpu::Buffer in bytes.
+    pub fn size(&self) -> wgpu::BufferAddress {
+        self.buffer.size()
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// Write data starting from element offset. The length grows if the data is written past
+    /// the current length. The byte offset must be a multiple of wgpu::COPY_BUFFER_ALIGNMENT.
+    /// An unaligned data size is padded with zeros if the write reaches the end of the written
+    /// elements, otherwise it's an error.
+    pub fn write(&mut self, queue: &wgpu::Queue, offset: usize, data: &[T]) -> Result<(), GpuBufferError> {
+        assert!(offset + data.len() <= self.capacity,
+                "GpuBuffer::write: {}..{} exceeds the capacity {}.", offset, offset + data.len(), self.capacity);
+
+        let byte_offset = self.byte_offset(offset);
+        if byte_offset % wgpu::COPY_BUFFER_ALIGNMENT != 0 {
+            return Err(GpuBufferError::UnalignedOffset(byte_offset));
+        }
+
+        let size = self.byte_offset(data.len());
+        if size % wgpu::COPY_BUFFER_ALIGNMENT == 0 {
+            queue.write_buffer(&self.buffer, byte_offset, bytemuck::cast_slice(data));
+        }
+        // The padding only overwrites bytes past the written elements.
+        else if offset + data.len() >= self.len {
+            queue.write_buffer(&self.buffer, byte_offset, &padded_bytes(data));
+        }
+        else {
+            return Err(GpuBufferError::UnalignedSize(size));
+        }
+
+        self.len = self.len.max(offset + data.len());
+        Ok(())
+    }
+
+    /// Replace the content with data. The buffer is reallocated if it's too small. Returns true
+    /// if the buffer was reallocated (bind groups that use the buffer must be recreated).
+    pub fn set_data(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, data: &[T]) -> bool {
+        self.len = 0;
+        let reallocated = self.reserve(device, queue, data.len());
+        // Writing from 0 over an empty buffer is always aligned or can be padded.
+        self.write(queue, 0, data).expect("GpuBuffer::set_data");
+        reallocated
+    }
+
+    /// Make room for at least capacity elements. The capacity grows to the next power of two
+    /// and the written elements are copied to the new buffer. Returns true if the buffer was
+    /// reallocated.
+    pub fn reserve(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, capacity: usize) -> bool {
+
+        let new_capacity = match grown_capacity(self.capacity, capacity) {
+            Some(new_capacity) => new_capacity,
+            None => return false,
+        };
+        let new_buffer = Self::create_buffer(device, new_capacity, self.usage, self.label.as_deref());
+
+        if self.len > 0 {
+            assert!(self.usage.contains(wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST),
+                    "GpuBuffer::reserve: growing a non-empty buffer requires COPY_SRC and COPY_DST usages.");
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("GpuBuffer grow encoder") });
+            let copy_size = align_copy_size(self.byte_offset(self.len));
+            encoder.copy_buffer_to_buffer(&self.buffer, 0, &new_buffer, 0, copy_size);
+            queue.submit(Some(encoder.finish()));
+        }
+
+        self.buffer = new_buffer;
+        self.capacity = new_capacity;
+        true
+    }
+
+    /// A slice of the buffer in elements. Unbounded ranges end at the length. Panics if the
+    /// range is empty (like 3..3 or .. of an empty buffer), since wgpu doesn't allow empty slices.
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> wgpu::BufferSlice {
+        let range = self.nonempty_element_range(range, "GpuBuffer::slice");
+        self.buffer.slice(self.byte_offset(range.start)..self.byte_offset(range.end))
+    }
+
+    /// The whole buffer as a binding resource.
+    pub fn as_entire_binding(&self) -> wgpu::BindingResource {
+        self.buffer.as_entire_binding()
+    }
+
+    /// A range of elements as a binding resource. Panics if the range is empty (see slice).
+    pub fn binding<R: RangeBounds<usize>>(&self, range: R) -> wgpu::BindingResource {
+        let range = self.nonempty_element_range(range, "GpuBuffer::binding");
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset: self.byte_offset(range.start),
+            size: wgpu::BufferSize::new(self.byte_offset(range.end - range.start)),
+        })
+    }
+
+    /// Copy the written elements back to the cpu.
+    pub async fn to_vec(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<T> {
+        if self.len == 0 { return Vec::new(); }
+        let size = self.byte_offset(self.len);
+        let data = to_vec::<u8>(device, queue, &self.buffer, 0, align_copy_size(size)).await;
+        vec_from_bytes(&data[..size as usize])
+    }
+
+    /// Start a non-blocking readback of a range of elements. Use ReadbackHandle::to_vec::<T>
+    /// for the typed result.
+    pub fn readback<R: RangeBounds<usize>>(&self, pool: &ReadbackPool, device: &wgpu::Device, queue: &wgpu::Queue, range: R) -> ReadbackHandle {
+        let range = self.nonempty_element_range(range, "GpuBuffer::readback");
+        pool.read(device, queue, &self.buffer, self.byte_offset(range.start), self.byte_offset(range.end - range.start))
+    }
+
+    fn byte_offset(&self, elements: usize) -> wgpu::BufferAddress {
+        (elements * std::mem::size_of::<T>()) as wgpu::BufferAddress
+    }
+
+    fn nonempty_element_range<R: RangeBounds<usize>>(&self, range: R, caller: &str) -> Range<usize> {
+        nonempty_element_range(range, self.len, self.capacity, caller)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_data_padding() {
+        assert_eq!(padded_bytes::<u8>(&[1, 2, 3]), vec![1, 2, 3, 0]);
+        assert_eq!(padded_bytes::<u16>(&[1, 2, 3]).len(), 8);
+        assert_eq!(padded_bytes::<u32>(&[1, 2]).len(), 8);
+    }
+
+    #[test]
+    fn element_ranges() {
+        assert_eq!(element_range(.., 5, 8), 0..5);
+        assert_eq!(element_range(2.., 5, 8), 2..5);
+        assert_eq!(element_range(1..=3, 5, 8), 1..4);
+        assert_eq!(element_range(..8, 5, 8), 0..8);
+        // An empty buffer gives an empty range instead of panicking.
+        assert_eq!(element_range(.., 0, 8), 0..0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn element_range_past_capacity() {
+        element_range(0..9, 5, 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "GpuBuffer::slice: the element range 3..3 is empty.")]
+    fn empty_slice_range() {
+        nonempty_element_range(3..3, 5, 8, "GpuBuffer::slice");
+    }
+
+    #[test]
+    #[should_panic(expected = "GpuBuffer::binding: the element range 0..0 is empty.")]
+    fn empty_binding_range() {
+        nonempty_element_range(.., 0, 8, "GpuBuffer::binding");
+    }
+
+    #[test]
+    fn reserve_growth() {
+        assert_eq!(grown_capacity(8, 8), None);
+        assert_eq!(grown_capacity(8, 4), None);
+        assert_eq!(grown_capacity(8, 9), Some(16));
+        assert_eq!(grown_capacity(1, 100), Some(128));
+    }
+}
@@ -0,0 +1,321 @@
+use std::fmt;
+use std::io::BufWriter;
+use std::fs::File;
+use std::path::Path;
+use crate::app_config::ColorSpace;
+use crate::mipmap::MipFilter;
+
+/// Write 8-bit rgba data to a png file. The data must be tightly packed (width * 4 bytes per row).
+pub fn write_png_rgba8(path: &Path, width: u32, height: u32, data: &[u8]) -> Result<(), png::EncodingError> {
+
+    if data.len() != width as usize * height as usize * 4 {
+        return Err(png::EncodingError::Format(
+            format!("write_png_rgba8: {} bytes don't match the image size {}x{}.", data.len(), width, height).into()));
+    }
+
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+
+    Ok(())
+}
+
+/// Swap the red and blue components of 8-bit bgra/rgba texels in place.
+pub fn swap_red_blue(data: &mut [u8]) {
+    for texel in data.chunks_exact_mut(4) {
+        texel.swap(0, 2);
+    }
+}
+
+/// Read a png file as 8-bit rgba data. Returns (width, height, data). Grayscale, rgb and
+/// palette images are expanded to rgba and 16-bit images are scaled down to 8 bits.
+pub fn read_png_rgba8(path: &Path) -> Result<(u32, u32, Vec<u8>), png::DecodingError> {
+
+    let file = File::open(path)?;
+    let decoder = png::Decoder::new(file);
+    let (info, mut reader) = decoder.read_info()?;
+
+    let mut buffer: Vec<u8> = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buffer)?;
+
+    let data = match info.color_type {
+        png::ColorType::RGBA => buffer,
+        png::ColorType::RGB => buffer.chunks_exact(3).flat_map(|c| [c[0], c[1], c[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => buffer.chunks_exact(2).flat_map(|c| [c[0], c[0], c[0], c[1]]).collect(),
+        png::ColorType::Grayscale => buffer.iter().flat_map(|&c| [c, c, c, 255]).collect(),
+        png::ColorType::Indexed => return Err(png::DecodingError::Other("Indexed png wasn't expanded.".into())),
+    };
+
+    Ok((info.width, info.height, data))
+}
+
+/// An error of image loading.
+#[derive(Debug)]
+pub enum ImageError {
+    Decoding(png::DecodingError),
+    /// The image or the requested texture format isn't supported.
+    Unsupported(String),
+    /// The data is malformed.
+    Invalid(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::Decoding(e) => write!(f, "Couldn't decode the image: {}", e),
+            ImageError::Unsupported(e) => write!(f, "Unsupported image: {}", e),
+            ImageError::Invalid(e) => write!(f, "Invalid image: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+impl From<png::DecodingError> for ImageError {
+    fn from(e: png::DecodingError) -> Self {
+        ImageError::Decoding(e)
+    }
+}
+
+/// Options for Texture::from_image_bytes.
+#[derive(Clone, Debug)]
+pub struct ImageOptions {
+    /// The texture format. None picks Rgba16Float for 16-bit images (the decoded values are
+    /// linear, so sRGB images keep their precision), Rgba8UnormSrgb for 8-bit sRGB images and
+    /// Rgba8Unorm for 8-bit linear images. An explicit 8-bit format quantizes 16-bit images.
+    pub format: Option<wgpu::TextureFormat>,
+    /// Srgb: the image is gamma encoded (colors). Linear: the values are used as they are
+    /// (normal maps, height maps, masks). The alpha channel is always linear.
+    pub color_space: ColorSpace,
+    /// Multiply the color components by alpha.
+    pub premultiply_alpha: bool,
+    pub usage: wgpu::TextureUsages,
+    /// Generate the full mip chain with the filter. The format must be renderable.
+    pub mipmaps: Option<MipFilter>,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            format: None,
+            color_space: ColorSpace::Srgb,
+            premultiply_alpha: false,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            mipmaps: None,
+        }
+    }
+}
+
+impl ImageOptions {
+
+    /// The texture format for the image.
+    pub fn texture_format(&self, image: &LinearImage) -> wgpu::TextureFormat {
+        self.format.unwrap_or(match (self.color_space, image.bit_depth) {
+            (_, 16) => wgpu::TextureFormat::Rgba16Float,
+            (ColorSpace::Srgb, _) => wgpu::TextureFormat::Rgba8UnormSrgb,
+            (ColorSpace::Linear, _) => wgpu::TextureFormat::Rgba8Unorm,
+        })
+    }
+}
+
+/// A decoded image as linear rgba values in [0, 1].
+pub struct LinearImage {
+    pub width: u32,
+    pub height: u32,
+    /// The bit depth of the source image (8 or 16).
+    pub bit_depth: u8,
+    pub texels: Vec<[f32; 4]>,
+}
+
+/// Decode a png image. Grayscale, gray+alpha, rgb, rgba and palette images with 1 to 16 bits
+/// per sample are expanded to rgba. If color_space is Srgb, the color components are
+/// converted to linear.
+pub fn decode_png(bytes: &[u8], color_space: ColorSpace) -> Result<LinearImage, ImageError> {
+
+    let mut decoder = png::Decoder::new(std::io::Cursor::new(bytes));
+    // Expand palettes, low bit depths and transparency chunks, but keep 16-bit samples.
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let (info, mut reader) = decoder.read_info()?;
+
+    let mut buffer: Vec<u8> = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buffer)?;
+
+    // The samples in [0, 1]. 16-bit samples are big endian.
+    let samples: Vec<f32> = match info.bit_depth {
+        png::BitDepth::Eight => buffer.iter().map(|&v| v as f32 / 255.0).collect(),
+        png::BitDepth::Sixteen => buffer.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]]) as f32 / 65535.0).collect(),
+        d => return Err(ImageError::Unsupported(format!("bit depth {:?} after expansion", d))),
+    };
+
+    let texels: Vec<[f32; 4]> = match info.color_type {
+        png::ColorType::RGBA => samples.chunks_exact(4).map(|c| [c[0], c[1], c[2], c[3]]).collect(),
+        png::ColorType::RGB => samples.chunks_exact(3).map(|c| [c[0], c[1], c[2], 1.0]).collect(),
+        png::ColorType::GrayscaleAlpha => samples.chunks_exact(2).map(|c| [c[0], c[0], c[0], c[1]]).collect(),
+        png::ColorType::Grayscale => samples.iter().map(|&c| [c, c, c, 1.0]).collect(),
+        png::ColorType::Indexed => return Err(ImageError::Unsupported("the palette wasn't expanded".into())),
+    };
+
+    let texels = match color_space {
+        ColorSpace::Srgb => texels.iter().map(|t| [srgb_to_linear(t[0]), srgb_to_linear(t[1]), srgb_to_linear(t[2]), t[3]]).collect(),
+        ColorSpace::Linear => texels,
+    };
+
+    Ok(LinearImage {
+        width: info.width,
+        height: info.height,
+        bit_depth: info.bit_depth as u8,
+        texels,
+    })
+}
+
+/// Multiply the color components of linear texels by alpha.
+pub fn premultiply_alpha(texels: &mut [[f32; 4]]) {
+    for t in texels.iter_mut() {
+        t[0] *= t[3];
+        t[1] *= t[3];
+        t[2] *= t[3];
+    }
+}
+
+/// Convert linear texels to the texel data of the format. The color components are gamma
+/// encoded for Srgb formats. R and Rg formats take the first components. Supports the 8-bit
+/// and 16-bit unorm formats and the 16-bit and 32-bit float formats with 1, 2 or 4 components
+/// and Bgra8.
+pub fn encode_texels(texels: &[[f32; 4]], format: wgpu::TextureFormat) -> Result<Vec<u8>, ImageError> {
+
+    use wgpu::TextureFormat as F;
+
+    let srgb = format.is_srgb();
+    let encode = |t: &[f32; 4]| -> [f32; 4] {
+        if srgb { [linear_to_srgb(t[0]), linear_to_srgb(t[1]), linear_to_srgb(t[2]), t[3]] } else { *t }
+    };
+
+    let unorm8 = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let unorm16 = |v: f32| (v.clamp(0.0, 1.0) * 65535.0).round() as u16;
+
+    let (components, swap_red_blue) = match format {
+        F::R8Unorm | F::R16Unorm | F::R16Float | F::R32Float => (1, false),
+        F::Rg8Unorm | F::Rg16Unorm | F::Rg16Float | F::Rg32Float => (2, false),
+        F::Rgba8Unorm | F::Rgba8UnormSrgb | F::Rgba16Unorm | F::Rgba16Float | F::Rgba32Float => (4, false),
+        F::Bgra8Unorm | F::Bgra8UnormSrgb => (4, true),
+        f => return Err(ImageError::Unsupported(format!("the texture format {:?}", f))),
+    };
+
+    let mut data: Vec<u8> = Vec::with_capacity(texels.len() * format.block_size(None).unwrap_or(4) as usize);
+
+    for t in texels.iter() {
+        let mut t = encode(t);
+        if swap_red_blue { t.swap(0, 2); }
+        for &v in t[..components].iter() {
+            match format {
+                F::R8Unorm | F::Rg8Unorm | F::Rgba8Unorm | F::Rgba8UnormSrgb | F::Bgra8Unorm | F::Bgra8UnormSrgb => data.push(unorm8(v)),
+                F::R16Unorm | F::Rg16Unorm | F::Rgba16Unorm => data.extend_from_slice(&unorm16(v).to_le_bytes()),
+                F::R16Float | F::Rg16Float | F::Rgba16Float => data.extend_from_slice(&f32_to_f16(v).to_le_bytes()),
+                _ => data.extend_from_slice(&v.to_le_bytes()),
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+pub fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 }
+}
+
+/// Convert f32 to the bits of f16 (round to nearest).
+pub(crate) fn f32_to_f16(value: f32) -> u16 {
+
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    // Inf and NaN.
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let e = exponent - 127 + 15;
+
+    // Overflow.
+    if e >= 0x1f { return sign | 0x7c00; }
+
+    // Subnormals and underflow.
+    if e <= 0 {
+        if e < -10 { return sign; }
+        let m = (mantissa | 0x80_0000) >> (1 - e);
+        return sign | ((m >> 13) + ((m >> 12) & 1)) as u16;
+    }
+
+    // The rounding carry may propagate to the exponent, which is correct.
+    let half = sign | ((e as u16) << 10) | (mantissa >> 13) as u16;
+    if mantissa & 0x1000 != 0 { half + 1 } else { half }
+}
+
+/// Convert the bits of f16 to f32.
+pub(crate) fn f16_to_f32(half: u16) -> f32 {
+
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f => if mantissa == 0.0 { sign * f32::INFINITY } else { f32::NAN },
+        e => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(e - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_floats() {
+        assert_eq!(f32_to_f16(0.0), 0);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(0.5), 0x3800);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f32_to_f16(1.0e6), 0x7c00);
+        assert_eq!(f32_to_f16(5.960_464_5e-8), 0x0001);
+        for v in [0.0, 1.0, -2.5, 0.333, 1.0e-6, 60000.0] {
+            let half = f32_to_f16(v);
+            assert_eq!(f32_to_f16(f16_to_f32(half)), half);
+        }
+    }
+
+    #[test]
+    fn default_texture_formats() {
+        let image = |bit_depth| LinearImage { width: 1, height: 1, bit_depth, texels: vec![[0.0; 4]] };
+        let srgb = ImageOptions::default();
+        let linear = ImageOptions { color_space: ColorSpace::Linear, ..ImageOptions::default() };
+        assert_eq!(srgb.texture_format(&image(8)), wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(srgb.texture_format(&image(16)), wgpu::TextureFormat::Rgba16Float);
+        assert_eq!(linear.texture_format(&image(8)), wgpu::TextureFormat::Rgba8Unorm);
+        assert_eq!(linear.texture_format(&image(16)), wgpu::TextureFormat::Rgba16Float);
+    }
+
+    #[test]
+    fn encode_srgb_roundtrip() {
+        let texels: Vec<[f32; 4]> = (0..=255u8).map(|v| {
+            let c = srgb_to_linear(v as f32 / 255.0);
+            [c, c, c, v as f32 / 255.0]
+        }).collect();
+        let data = encode_texels(&texels, wgpu::TextureFormat::Rgba8UnormSrgb).unwrap();
+        for (v, t) in data.chunks_exact(4).enumerate() {
+            assert_eq!(t, &[v as u8; 4]);
+        }
+        let bgra = encode_texels(&[[1.0, 0.0, 0.5, 1.0]], wgpu::TextureFormat::Bgra8Unorm).unwrap();
+        assert_eq!(bgra, vec![128, 0, 255, 255]);
+        assert!(encode_texels(&texels, wgpu::TextureFormat::Depth32Float).is_err());
+    }
+}
@@ -254,14 +254,36 @@ impl InputCache {
         else { PhysicalPosition::<f64>::new(0.0, 0.0) }
     }
 
+    /// Is any key or mouse button pressed, down or released, or did the mouse move. Used by
+    /// the on-demand redraw mode to keep redrawing while the user interacts.
+    pub fn is_active(&self) -> bool {
+        self.mouse_moved ||
+        !self.keyboard.is_empty() ||
+        self.mouse_buttons.left.state.is_some() ||
+        self.mouse_buttons.middle.state.is_some() ||
+        self.mouse_buttons.right.state.is_some()
+    }
+
     /// This should be called before the actual update to ensure the all events takes effect even
     /// winit doesn't produce any events.
     pub fn pre_update(&mut self) {
+        let now = self.timer.elapsed().as_nanos();
+        self.advance(now);
+    }
+
+    /// The same as pre_update, but the time is advanced by time_delta nanoseconds instead of
+    /// reading the timer. Used by loops that need a deterministic clock.
+    pub fn pre_update_with_delta(&mut self, time_delta: u128) {
+        let now = self.time_now + time_delta;
+        self.advance(now);
+    }
+
+    /// Update the time and the button states.
+    fn advance(&mut self, now: u128) {
         
         self.mouse_moved = false;
 
         // Update timer.
-        let now = self.timer.elapsed().as_nanos();
         self.time_delta = now - self.time_now;
         self.time_now = now;
 
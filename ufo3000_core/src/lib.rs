@@ -1,9 +1,22 @@
 pub mod logger;
 pub mod template;
+pub mod app_config;
 pub mod input;
 pub mod camera;
 pub mod buffer;
+pub mod gpu_buffer;
+pub mod uniform_ring;
+pub mod ping_pong;
+pub mod readback;
 pub mod misc;
 pub mod screen;
 pub mod texture;
 pub mod render_object;
+pub mod image;
+pub mod float_image;
+pub mod mipmap;
+pub mod fixed_step;
+pub mod frame_stats;
+pub mod profiler;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod golden;
@@ -1,5 +1,6 @@
 use simple_logger::SimpleLogger;
 use log::LevelFilter;
+use crate::frame_stats::FrameSummary;
 
 /// Initialize simple logger.
 pub fn initialize_simple_logger(module_levels: &Vec<(String, LevelFilter)>) {
@@ -16,3 +17,8 @@ pub fn initialize_simple_logger(module_levels: &Vec<(String, LevelFilter)>) {
         simple_logger.with_utc_timestamps().init().unwrap();
     }
 }
+
+/// Log a frame timing summary (see FrameStats::set_report_interval).
+pub fn log_frame_summary(summary: &FrameSummary) {
+    log::info!("{}", summary);
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use wgpu::StoreOp;
+use crate::render_object::{RenderObject, RenderPipelineBuilder, RenderPassBuilder};
+
+/// The downsampling filter of the mip chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MipFilter {
+    /// The average of 2x2 texels, or of the 2-3 texels that each output texel covers along
+    /// an odd sized axis.
+    Box,
+    /// A Kaiser windowed sinc over 6x6 texels. Sharper than Box.
+    Kaiser,
+}
+
+const MIPMAP_SHADER: &str = r#"
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // A triangle that covers the whole target.
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+
+fn load(coord: vec2<i32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(source));
+    return textureLoad(source, clamp(coord, vec2<i32>(0), size - 1), 0);
+}
+
+// The weights of the source texels 2x, 2x + 1 and 2x + 2 along an axis of size n. An odd n
+// has n / 2 output texels that each cover 2 + 1 / (n / 2) source texels.
+fn box_weights(x: i32, n: i32) -> vec3<f32> {
+    if (n == 1 || n % 2 == 0) {
+        return vec3<f32>(0.5, 0.5, 0.0);
+    }
+    let k = f32(n / 2);
+    return vec3<f32>(k - f32(x), k, f32(x) + 1.0) / f32(n);
+}
+
+@fragment
+fn fs_box(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(source));
+    let xy = vec2<i32>(in.position.xy);
+    let wx = box_weights(xy.x, size.x);
+    let wy = box_weights(xy.y, size.y);
+    var color = vec4<f32>(0.0);
+    for (var y = 0; y < 3; y++) {
+        for (var x = 0; x < 3; x++) {
+            color += wx[x] * wy[y] * load(xy * 2 + vec2<i32>(x, y));
+        }
+    }
+    return color;
+}
+
+// Kaiser windowed sinc (width 3, alpha 4) at the distances 2.5, 1.5, 0.5, 0.5, 1.5 and 2.5.
+var<private> KAISER: array<f32, 6> = array<f32, 6>(-0.020992482, 0.094502333, 0.426490149, 0.426490149, 0.094502333, -0.020992482);
+
+@fragment
+fn fs_kaiser(in: VertexOutput) -> @location(0) vec4<f32> {
+    let base = vec2<i32>(in.position.xy) * 2 - 2;
+    var color = vec4<f32>(0.0);
+    for (var y = 0; y < 6; y++) {
+        for (var x = 0; x < 6; x++) {
+            color += KAISER[x] * KAISER[y] * load(base + vec2<i32>(x, y));
+        }
+    }
+    return max(color, vec4<f32>(0.0));
+}
+"#;
+
+/// The number of mip levels of a full mip chain.
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Generates the mip chain of 2d textures by rendering each level from the previous one.
+///
+/// The texture needs TEXTURE_BINDING and RENDER_ATTACHMENT usages and a renderable format.
+/// The levels are read and written through views of the texture format, so Srgb textures are
+/// filtered in linear space. The pipelines are kept per format and filter, so create one
+/// generator and pass it to Texture::generate_mipmaps_with.
+pub struct MipmapGenerator {
+    shader: wgpu::ShaderModule,
+    pipelines: HashMap<(wgpu::TextureFormat, MipFilter), RenderObject>,
+}
+
+impl MipmapGenerator {
+
+    pub fn init(device: &wgpu::Device) -> Self {
+        Self {
+            shader: device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Mipmap shader"),
+                source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(MIPMAP_SHADER)),
+            }),
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// Record the passes that fill mip levels 1.. of every array layer from level 0.
+    pub fn generate(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture, filter: MipFilter) {
+
+        assert!(texture.dimension() == wgpu::TextureDimension::D2, "MipmapGenerator: only 2d textures are supported.");
+        assert!(texture.usage().contains(wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT),
+                "MipmapGenerator: the texture needs TEXTURE_BINDING and RENDER_ATTACHMENT usages.");
+
+        let format = texture.format();
+        let shader = &self.shader;
+        let render_object = self.pipelines.entry((format, filter)).or_insert_with(|| {
+            RenderPipelineBuilder::new(format)
+                .with_fragment_entry(Some(match filter { MipFilter::Box => "fs_box", MipFilter::Kaiser => "fs_kaiser" }))
+                .with_write_mask(wgpu::ColorWrites::ALL)
+                .with_depth_stencil(None)
+                .build(device,
+                       shader,
+                       &vec![vec![wgpu::BindGroupLayoutEntry {
+                           binding: 0,
+                           visibility: wgpu::ShaderStages::FRAGMENT,
+                           ty: wgpu::BindingType::Texture {
+                               sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                               view_dimension: wgpu::TextureViewDimension::D2,
+                               multisampled: false,
+                           },
+                           count: None,
+                       }]],
+                       Some("Mipmap pipeline"))
+        });
+
+        let level_view = |level: u32, layer: u32| texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Mipmap view"),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_mip_level: level,
+            mip_level_count: Some(1),
+            base_array_layer: layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        });
+
+        for layer in 0..texture.depth_or_array_layers() {
+            for level in 1..texture.mip_level_count() {
+
+                let source = level_view(level - 1, layer);
+                let target = level_view(level, layer);
+
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Mipmap bind group"),
+                    layout: &render_object.bind_group_layouts[0],
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&source),
+                    }],
+                });
+
+                let mut render_pass = RenderPassBuilder::new()
+                    .with_label("Mipmap pass")
+                    .with_color(&target, wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT), StoreOp::Store)
+                    .begin(encoder);
+                render_pass.set_pipeline(&render_object.pipeline);
+                render_pass.set_bind_group(0, &bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_level_counts() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(256, 256), 9);
+        assert_eq!(mip_level_count(300, 20), 9);
+        assert_eq!(mip_level_count(0, 0), 1);
+    }
+}
@@ -2,10 +2,20 @@ use bytemuck::{Pod, Zeroable};
 
 /// A trait for types that can be copied from wgpu::buffer buffer to
 /// a std::Vec. // TODO: check if there is already an implementation for this.
+/// Prefer vec_from_bytes, which works for every Pod type and doesn't require aligned data.
 pub trait Convert2Vec where Self: std::marker::Sized {
     fn convert(data: &[u8]) -> Vec<Self>;  
 }
 
+/// Convert bytes into a vector of T. The length of data must be a multiple of the size of T.
+pub fn vec_from_bytes<T: Pod>(data: &[u8]) -> Vec<T> {
+    let size = std::mem::size_of::<T>();
+    assert!(size > 0 && data.len() % size == 0, "vec_from_bytes: the data length isn't a multiple of the type size.");
+    let mut result = vec![T::zeroed(); data.len() / size];
+    bytemuck::cast_slice_mut::<T, u8>(&mut result).copy_from_slice(data);
+    result
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
 pub struct OutputVertex {
@@ -29,6 +39,7 @@ macro_rules! impl_convert {
 }
 
 impl_convert!{OutputVertex}
+impl_convert!{u8}
 impl_convert!{u32}
 impl_convert!{f32}
 
@@ -94,3 +105,14 @@ pub fn clamp(val: f32, min: f32, max: f32) -> f32 {
     
     if result <= min { min } else { val }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vec_from_unaligned_bytes() {
+        let bytes: Vec<u8> = [0u8].iter().chain(bytemuck::cast_slice::<u32, u8>(&[7, 8])).copied().collect();
+        assert_eq!(vec_from_bytes::<u32>(&bytes[1..]), vec![7, 8]);
+    }
+}
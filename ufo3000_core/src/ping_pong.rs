@@ -0,0 +1,109 @@
+use bytemuck::Pod;
+use crate::gpu_buffer::GpuBuffer;
+use crate::render_object::{ComputeObject, create_bind_groups};
+use crate::texture::Texture;
+
+/// A resource that can be bound as a whole.
+pub trait PingPongResource {
+    fn binding(&self) -> wgpu::BindingResource;
+}
+
+impl PingPongResource for wgpu::Buffer {
+    fn binding(&self) -> wgpu::BindingResource {
+        self.as_entire_binding()
+    }
+}
+
+impl<T: Pod> PingPongResource for GpuBuffer<T> {
+    fn binding(&self) -> wgpu::BindingResource {
+        self.as_entire_binding()
+    }
+}
+
+impl PingPongResource for Texture {
+    fn binding(&self) -> wgpu::BindingResource {
+        wgpu::BindingResource::TextureView(&self.view)
+    }
+}
+
+/// A binding of a PingPong bind group.
+pub enum PingPongBinding<'a> {
+    /// The current resource (the input of the next step).
+    Read,
+    /// The previous resource (the output of the next step).
+    Write,
+    /// Any other resource.
+    Resource(wgpu::BindingResource<'a>),
+}
+
+/// Two buffers or textures for iterative compute passes that read from one resource and write
+/// to the other. The bind groups for both directions are created once.
+///
+/// Per step: dispatch with bind_groups, then call swap. After the swap current holds the
+/// result of the step.
+pub struct PingPong<T: PingPongResource> {
+    resources: [T; 2],
+    bind_groups: [Vec<wgpu::BindGroup>; 2],
+    current: usize,
+}
+
+impl<T: PingPongResource> PingPong<T> {
+
+    /// Create the bind groups for the bind group layouts of the compute object. bindings has
+    /// the same shape as ComputeObject::bind_group_layout_entries. resources[0] is the initial
+    /// current resource.
+    pub fn init(device: &wgpu::Device,
+                compute_object: &ComputeObject,
+                resources: [T; 2],
+                bindings: &Vec<Vec<PingPongBinding>>) -> Self {
+
+        assert!(bindings.len() == compute_object.bind_group_layout_entries.len(),
+                "PingPong::init: expected {} bind groups, got {}.", compute_object.bind_group_layout_entries.len(), bindings.len());
+
+        let create = |read: &T, write: &T| {
+            let resources: Vec<Vec<wgpu::BindingResource>> = bindings.iter().map(|group| {
+                group.iter().map(|b| match b {
+                    PingPongBinding::Read => read.binding(),
+                    PingPongBinding::Write => write.binding(),
+                    PingPongBinding::Resource(r) => r.clone(),
+                }).collect()
+            }).collect();
+            let resource_refs = resources.iter().map(|group| group.iter().collect()).collect();
+            create_bind_groups(device,
+                               &compute_object.bind_group_layout_entries,
+                               &compute_object.bind_group_layouts,
+                               &resource_refs)
+        };
+
+        let bind_groups = [
+            create(&resources[0], &resources[1]),
+            create(&resources[1], &resources[0]),
+        ];
+
+        Self {
+            resources,
+            bind_groups,
+            current: 0,
+        }
+    }
+
+    /// Exchange the current and the previous resource.
+    pub fn swap(&mut self) {
+        self.current = 1 - self.current;
+    }
+
+    /// The resource that holds the latest result.
+    pub fn current(&self) -> &T {
+        &self.resources[self.current]
+    }
+
+    /// The other resource. The next step writes to it.
+    pub fn previous(&self) -> &T {
+        &self.resources[1 - self.current]
+    }
+
+    /// The bind groups that read the current and write the previous resource.
+    pub fn bind_groups(&self) -> &Vec<wgpu::BindGroup> {
+        &self.bind_groups[self.current]
+    }
+}
@@ -0,0 +1,255 @@
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+const MAP_PENDING: u8 = 0;
+const MAP_DONE: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+/// The gpu time of a profiler scope.
+#[derive(Clone, Debug)]
+pub struct GpuTiming {
+    pub label: String,
+    pub milliseconds: f64,
+}
+
+/// A timed compute or render pass. Pass Some(&scope) to ComputeObject::dispatch*,
+/// create_compute_pass, create_render_pass or RenderPassBuilder::with_scope. A scope of a
+/// disabled profiler doesn't write any timestamps.
+pub struct GpuScope<'a> {
+    query_set: Option<&'a wgpu::QuerySet>,
+    index: u32,
+}
+
+impl<'a> GpuScope<'a> {
+
+    /// The query indices of the beginning and the end of the pass.
+    fn query_indices(&self) -> (u32, u32) {
+        (self.index * 2, self.index * 2 + 1)
+    }
+
+    /// The timestamp writes for a compute pass descriptor.
+    pub fn compute_timestamp_writes(&self) -> Option<wgpu::ComputePassTimestampWrites<'a>> {
+        let (beginning, end) = self.query_indices();
+        self.query_set.map(|query_set| wgpu::ComputePassTimestampWrites {
+            query_set,
+            beginning_of_pass_write_index: Some(beginning),
+            end_of_pass_write_index: Some(end),
+        })
+    }
+
+    /// The timestamp writes for a render pass descriptor.
+    pub fn render_timestamp_writes(&self) -> Option<wgpu::RenderPassTimestampWrites<'a>> {
+        let (beginning, end) = self.query_indices();
+        self.query_set.map(|query_set| wgpu::RenderPassTimestampWrites {
+            query_set,
+            beginning_of_pass_write_index: Some(beginning),
+            end_of_pass_write_index: Some(end),
+        })
+    }
+}
+
+/// Add the label of a new scope. Returns the scope index or None if max_scopes is exceeded.
+fn allocate_scope(labels: &mut Vec<String>, max_scopes: u32, label: &str) -> Option<u32> {
+    if labels.len() as u32 >= max_scopes {
+        return None;
+    }
+    labels.push(label.to_owned());
+    Some(labels.len() as u32 - 1)
+}
+
+/// The state of the readback buffer.
+enum ReadState {
+    Idle,
+    /// The timestamps are copied to the read buffer, but the buffer isn't mapped yet.
+    Copied(Vec<String>),
+    /// Waiting for map_async.
+    Mapping(Vec<String>, Arc<AtomicU8>),
+}
+
+/// Measures the gpu time of compute and render passes with timestamp queries. Requires
+/// wgpu::Features::TIMESTAMP_QUERY. Without the feature all the methods are no-ops.
+///
+/// Per frame: create scopes with scope, record the passes, call resolve with the last
+/// encoder, submit, call end_frame and finally poll_results. The results arrive a few frames
+/// later. Frames that are recorded while the previous results are still mapped are skipped.
+pub struct GpuProfiler {
+    query_set: Option<wgpu::QuerySet>,
+    resolve_buffer: Option<wgpu::Buffer>,
+    read_buffer: Option<wgpu::Buffer>,
+    max_scopes: u32,
+    timestamp_period: f32,
+    labels: RefCell<Vec<String>>,
+    state: ReadState,
+    timings: Vec<GpuTiming>,
+}
+
+impl GpuProfiler {
+
+    /// Create a profiler for at most max_scopes scopes per frame.
+    pub fn init(device: &wgpu::Device, queue: &wgpu::Queue, max_scopes: u32) -> Self {
+
+        assert!(max_scopes > 0 && max_scopes * 2 <= wgpu::QUERY_SET_MAX_QUERIES, "GpuProfiler: invalid max_scopes.");
+
+        let enabled = device.features().contains(wgpu::Features::TIMESTAMP_QUERY);
+        if !enabled {
+            log::warn!("TIMESTAMP_QUERY isn't enabled. GpuProfiler is disabled.");
+        }
+
+        let size = (max_scopes * 2 * wgpu::QUERY_SIZE) as wgpu::BufferAddress;
+
+        let query_set = enabled.then(|| device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("GpuProfiler query set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: max_scopes * 2,
+        }));
+        let resolve_buffer = enabled.then(|| device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("GpuProfiler resolve buffer"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        }));
+        let read_buffer = enabled.then(|| device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("GpuProfiler read buffer"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+
+        Self {
+            query_set,
+            resolve_buffer,
+            read_buffer,
+            max_scopes,
+            timestamp_period: queue.get_timestamp_period(),
+            labels: RefCell::new(Vec::new()),
+            state: ReadState::Idle,
+            timings: Vec::new(),
+        }
+    }
+
+    /// Is the timestamp query feature available.
+    pub fn is_enabled(&self) -> bool {
+        self.query_set.is_some()
+    }
+
+    /// Create a scope for one pass. If the profiler is disabled or max_scopes is exceeded, the
+    /// scope doesn't write any timestamps.
+    pub fn scope(&self, label: &str) -> GpuScope {
+        if self.query_set.is_none() {
+            return GpuScope { query_set: None, index: 0 };
+        }
+        match allocate_scope(&mut self.labels.borrow_mut(), self.max_scopes, label) {
+            Some(index) => GpuScope { query_set: self.query_set.as_ref(), index },
+            None => GpuScope { query_set: None, index: 0 },
+        }
+    }
+
+    /// Resolve the timestamps of the scopes of this frame. Call this after the last scoped pass
+    /// has been recorded and before submitting the encoder.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+
+        let labels = self.labels.take();
+
+        let (query_set, resolve_buffer, read_buffer) = match (&self.query_set, &self.resolve_buffer, &self.read_buffer) {
+            (Some(q), Some(r), Some(b)) => (q, r, b),
+            _ => return,
+        };
+
+        // The previous results haven't been read yet.
+        if labels.is_empty() || !matches!(self.state, ReadState::Idle) { return; }
+
+        let count = labels.len() as u32 * 2;
+        encoder.resolve_query_set(query_set, 0..count, resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(resolve_buffer, 0, read_buffer, 0, (count * wgpu::QUERY_SIZE) as wgpu::BufferAddress);
+        self.state = ReadState::Copied(labels);
+    }
+
+    /// Start reading the resolved timestamps. Call this after submitting the encoder that was
+    /// passed to resolve.
+    pub fn end_frame(&mut self) {
+        if let ReadState::Copied(labels) = std::mem::replace(&mut self.state, ReadState::Idle) {
+            let map_state = Arc::new(AtomicU8::new(MAP_PENDING));
+            let callback_state = map_state.clone();
+            self.read_buffer.as_ref().unwrap().slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                callback_state.store(if result.is_ok() { MAP_DONE } else { MAP_FAILED }, Ordering::SeqCst);
+            });
+            self.state = ReadState::Mapping(labels, map_state);
+        }
+    }
+
+    /// Poll the device and read the timings if they are available. Returns true if timings was
+    /// updated.
+    pub fn poll_results(&mut self, device: &wgpu::Device) -> bool {
+
+        let map_state = match &self.state {
+            ReadState::Mapping(_, map_state) => map_state.clone(),
+            _ => return false,
+        };
+
+        device.poll(wgpu::Maintain::Poll);
+
+        match map_state.load(Ordering::SeqCst) {
+            MAP_PENDING => false,
+            MAP_FAILED => {
+                log::error!("GpuProfiler: couldn't map the timestamp buffer.");
+                self.state = ReadState::Idle;
+                false
+            }
+            _ => {
+                let labels = match std::mem::replace(&mut self.state, ReadState::Idle) {
+                    ReadState::Mapping(labels, _) => labels,
+                    _ => unreachable!(),
+                };
+                let read_buffer = self.read_buffer.as_ref().unwrap();
+                {
+                    let data = read_buffer.slice(..).get_mapped_range();
+                    let timestamps: &[u64] = bytemuck::cast_slice(&data);
+                    let period = self.timestamp_period as f64;
+                    self.timings = labels.into_iter().enumerate().map(|(i, label)| {
+                        let ticks = timestamps[i * 2 + 1].wrapping_sub(timestamps[i * 2]);
+                        GpuTiming { label, milliseconds: ticks as f64 * period / 1_000_000.0 }
+                    }).collect();
+                }
+                read_buffer.unmap();
+                true
+            }
+        }
+    }
+
+    /// The latest timings in the order of the scopes.
+    pub fn timings(&self) -> &[GpuTiming] {
+        &self.timings
+    }
+
+    /// The sum of the latest timings with the given label in milliseconds.
+    pub fn timing(&self, label: &str) -> Option<f64> {
+        let mut matching = self.timings.iter().filter(|t| t.label == label).peekable();
+        matching.peek()?;
+        Some(matching.map(|t| t.milliseconds).sum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scope_allocation() {
+        let mut labels = Vec::new();
+        assert_eq!(allocate_scope(&mut labels, 2, "a"), Some(0));
+        assert_eq!(allocate_scope(&mut labels, 2, "b"), Some(1));
+        assert_eq!(allocate_scope(&mut labels, 2, "c"), None);
+        assert_eq!(labels, vec!["a", "b"]);
+
+        let scope = GpuScope { query_set: None, index: 1 };
+        assert_eq!(scope.query_indices(), (2, 3));
+    }
+
+    #[test]
+    fn disabled_scope_writes_nothing() {
+        let scope = GpuScope { query_set: None, index: 0 };
+        assert!(scope.compute_timestamp_writes().is_none());
+        assert!(scope.render_timestamp_writes().is_none());
+    }
+}
@@ -0,0 +1,299 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use crate::misc::vec_from_bytes;
+use crate::template::Spawner;
+
+/// The smallest staging buffer size.
+const MIN_SIZE_CLASS: wgpu::BufferAddress = 256;
+
+type FreeBuffers = Rc<RefCell<HashMap<wgpu::BufferAddress, Vec<wgpu::Buffer>>>>;
+
+/// A pool of MAP_READ staging buffers for reading buffers back to the cpu without blocking.
+/// The staging buffers are grouped by size classes (powers of two) and returned to the pool
+/// when the readback has finished.
+///
+/// The mapping is finished by wgpu::Device::poll. The loops poll the device once per frame, so
+/// handles that are spawned with Spawner::spawn_local (or ReadbackHandle::on_complete)
+/// complete a frame or two later.
+pub struct ReadbackPool {
+    free: FreeBuffers,
+}
+
+impl Default for ReadbackPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReadbackPool {
+
+    pub fn new() -> Self {
+        Self {
+            free: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    /// Record a copy of size bytes from src_offset of the buffer. The offset doesn't need to be
+    /// aligned. Call PendingReadback::submitted after the encoder has been submitted.
+    pub fn copy(&self,
+                device: &wgpu::Device,
+                encoder: &mut wgpu::CommandEncoder,
+                buffer: &wgpu::Buffer,
+                src_offset: wgpu::BufferAddress,
+                size: wgpu::BufferAddress) -> PendingReadback {
+
+        assert!(size > 0, "ReadbackPool::copy: size must be > 0.");
+
+        // copy_buffer_to_buffer requires 4 byte aligned offsets and sizes.
+        let align = wgpu::COPY_BUFFER_ALIGNMENT;
+        let aligned_offset = src_offset / align * align;
+        let skip = src_offset - aligned_offset;
+        let copy_size = (skip + size + align - 1) / align * align;
+
+        assert!(aligned_offset + copy_size <= buffer.size(),
+                "ReadbackPool::copy: the range {}..{} is out of the buffer (size {}).", src_offset, src_offset + size, buffer.size());
+
+        let size_class = copy_size.next_power_of_two().max(MIN_SIZE_CLASS);
+        let staging_buffer = self.acquire(device, size_class);
+
+        encoder.copy_buffer_to_buffer(buffer, aligned_offset, &staging_buffer, 0, copy_size);
+
+        PendingReadback {
+            staging_buffer,
+            size_class,
+            copy_size,
+            skip: skip as usize,
+            len: size as usize,
+            free: self.free.clone(),
+        }
+    }
+
+    /// Copy size bytes from src_offset of the buffer with an own encoder and start reading.
+    pub fn read(&self,
+                device: &wgpu::Device,
+                queue: &wgpu::Queue,
+                buffer: &wgpu::Buffer,
+                src_offset: wgpu::BufferAddress,
+                size: wgpu::BufferAddress) -> ReadbackHandle {
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Readback encoder") });
+        let pending = self.copy(device, &mut encoder, buffer, src_offset, size);
+        queue.submit(Some(encoder.finish()));
+        pending.submitted()
+    }
+
+    /// The number of staging buffers that are waiting for reuse.
+    pub fn free_buffers(&self) -> usize {
+        self.free.borrow().values().map(|v| v.len()).sum()
+    }
+
+    /// Release the unused staging buffers.
+    pub fn clear(&self) {
+        self.free.borrow_mut().clear();
+    }
+
+    fn acquire(&self, device: &wgpu::Device, size_class: wgpu::BufferAddress) -> wgpu::Buffer {
+        if let Some(buffer) = self.free.borrow_mut().get_mut(&size_class).and_then(|v| v.pop()) {
+            return buffer;
+        }
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback staging buffer"),
+            size: size_class,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+}
+
+/// A recorded copy that hasn't been submitted yet.
+pub struct PendingReadback {
+    staging_buffer: wgpu::Buffer,
+    size_class: wgpu::BufferAddress,
+    copy_size: wgpu::BufferAddress,
+    skip: usize,
+    len: usize,
+    free: FreeBuffers,
+}
+
+impl PendingReadback {
+
+    /// Start mapping the staging buffer. The encoder of the copy must have been submitted.
+    pub fn submitted(self) -> ReadbackHandle {
+
+        let (sender, receiver) = map_channel();
+        self.staging_buffer.slice(..self.copy_size).map_async(wgpu::MapMode::Read, move |result| sender.send(result));
+
+        ReadbackHandle {
+            staging_buffer: Some(self.staging_buffer),
+            size_class: self.size_class,
+            copy_size: self.copy_size,
+            skip: self.skip,
+            len: self.len,
+            free: self.free,
+            receiver,
+        }
+    }
+}
+
+#[derive(Default)]
+struct MapState {
+    result: Option<Result<(), wgpu::BufferAsyncError>>,
+    waker: Option<Waker>,
+}
+
+/// Create a oneshot channel for the result of wgpu::BufferSlice::map_async.
+fn map_channel() -> (MapSender, MapReceiver) {
+    let state = Arc::new(Mutex::new(MapState::default()));
+    (MapSender(state.clone()), MapReceiver(state))
+}
+
+/// The sending half of the map_async oneshot channel. Moved into the map_async callback.
+struct MapSender(Arc<Mutex<MapState>>);
+
+impl MapSender {
+    fn send(self, result: Result<(), wgpu::BufferAsyncError>) {
+        let mut state = self.0.lock().unwrap();
+        state.result = Some(result);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// The receiving half of the map_async oneshot channel. Resolves to the result of the
+/// mapping.
+struct MapReceiver(Arc<Mutex<MapState>>);
+
+impl MapReceiver {
+    fn is_ready(&self) -> bool {
+        self.0.lock().unwrap().result.is_some()
+    }
+}
+
+impl Future for MapReceiver {
+    type Output = Result<(), wgpu::BufferAsyncError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.0.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Map the slice for reading and wait for the map_async callback. On native targets the
+/// device is polled until the mapping has finished, on the web the browser finishes it. The
+/// copy into the buffer must have been submitted.
+pub async fn map_read(device: &wgpu::Device, slice: wgpu::BufferSlice<'_>) -> Result<(), wgpu::BufferAsyncError> {
+
+    let (sender, receiver) = map_channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| sender.send(result));
+
+    #[cfg(not(target_arch = "wasm32"))]
+    device.poll(wgpu::Maintain::Wait);
+
+    #[cfg(target_arch = "wasm32")]
+    let _ = device;
+
+    receiver.await
+}
+
+/// A readback in flight. Resolves to the bytes of the requested range.
+pub struct ReadbackHandle {
+    staging_buffer: Option<wgpu::Buffer>,
+    size_class: wgpu::BufferAddress,
+    copy_size: wgpu::BufferAddress,
+    skip: usize,
+    len: usize,
+    free: FreeBuffers,
+    receiver: MapReceiver,
+}
+
+impl ReadbackHandle {
+
+    /// Has the mapping finished (successfully or not).
+    pub fn is_ready(&self) -> bool {
+        self.receiver.is_ready()
+    }
+
+    /// Await the data as a vector of T.
+    pub async fn to_vec<T: bytemuck::Pod>(self) -> Result<Vec<T>, wgpu::BufferAsyncError> {
+        self.await.map(|data| vec_from_bytes(&data))
+    }
+
+    /// Call the callback with the data when the readback has finished.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn on_complete<'a, F>(self, spawner: &Spawner<'a>, callback: F)
+        where F: FnOnce(Result<Vec<u8>, wgpu::BufferAsyncError>) + 'a {
+        spawner.spawn_local(async move { callback(self.await) });
+    }
+
+    /// Call the callback with the data when the readback has finished.
+    #[cfg(target_arch = "wasm32")]
+    pub fn on_complete<F>(self, spawner: &Spawner, callback: F)
+        where F: FnOnce(Result<Vec<u8>, wgpu::BufferAsyncError>) + 'static {
+        spawner.spawn_local(async move { callback(self.await) });
+    }
+
+    /// Copy the mapped data and return the staging buffer to the pool.
+    fn take_data(&mut self) -> Vec<u8> {
+        let staging_buffer = self.staging_buffer.take().expect("ReadbackHandle polled after completion.");
+        let data = {
+            let mapped = staging_buffer.slice(..self.copy_size).get_mapped_range();
+            mapped[self.skip..self.skip + self.len].to_vec()
+        };
+        staging_buffer.unmap();
+        self.free.borrow_mut().entry(self.size_class).or_default().push(staging_buffer);
+        data
+    }
+}
+
+impl Future for ReadbackHandle {
+    type Output = Result<Vec<u8>, wgpu::BufferAsyncError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.receiver).poll(cx) {
+            Poll::Ready(result) => Poll::Ready(result.map(|_| this.take_data())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_channel_resolves() {
+        let (sender, receiver) = map_channel();
+        assert!(!receiver.is_ready());
+        sender.send(Ok(()));
+        assert!(receiver.is_ready());
+        assert!(pollster::block_on(receiver).is_ok());
+    }
+
+    // Runs under wasm-pack test --node. The result is sent after the receiver is polled, like
+    // the map_async callback of the browser, so the waker path is exercised without an adapter.
+    #[cfg(target_arch = "wasm32")]
+    #[wasm_bindgen_test::wasm_bindgen_test]
+    async fn map_channel_wakes() {
+        let (sender, receiver) = map_channel();
+        wasm_bindgen_futures::spawn_local(async move { sender.send(Ok(())) });
+        assert!(!receiver.is_ready());
+        assert!(receiver.await.is_ok());
+
+        let (sender, receiver) = map_channel();
+        wasm_bindgen_futures::spawn_local(async move { sender.send(Err(wgpu::BufferAsyncError)) });
+        assert!(receiver.await.is_err());
+    }
+}
@@ -3,9 +3,11 @@ use wgpu::StoreOp;
 use crate::impl_convert;
 use crate::misc::Convert2Vec;
 use bytemuck::{Pod, Zeroable};
-use wgpu::util::RenderEncoder;
 use core::ops::Range;
 use crate::texture::Texture;
+use crate::profiler::GpuScope;
+use std::mem::size_of;
+use std::sync::Once;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
@@ -16,6 +18,16 @@ pub struct DrawIndirect {
     pub base_instance: u32, // The instance ID of the first instance to draw.
 }
 
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct DrawIndexedIndirect {
+    pub index_count: u32, // The number of indices to draw.
+    pub instance_count: u32, // The number of instances to draw.
+    pub base_index: u32, // The first index within the index buffer.
+    pub vertex_offset: i32, // The value added to the vertex index before indexing into the vertex buffer.
+    pub base_instance: u32, // The instance ID of the first instance to draw.
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
 pub struct DispatchIndirect {
@@ -25,6 +37,7 @@ pub struct DispatchIndirect {
 }
 
 impl_convert!{DrawIndirect}
+impl_convert!{DrawIndexedIndirect}
 impl_convert!{DispatchIndirect}
 
 pub struct ComputeObject {
@@ -77,21 +90,23 @@ impl ComputeObject {
 
     pub fn dispatch(&self,
                     bind_groups: &Vec<wgpu::BindGroup>,
+                    dynamic_offsets: &[&[u32]],
                     encoder: &mut wgpu::CommandEncoder,
                     x: u32,
                     y: u32,
                     z: u32,
-                    label: wgpu::Label) {
+                    label: wgpu::Label,
+                    scope: Option<&GpuScope>) {
 
         let mut pass = encoder.begin_compute_pass(
             &wgpu::ComputePassDescriptor {
                 label,
-                timestamp_writes: None,
+                timestamp_writes: scope.and_then(|s| s.compute_timestamp_writes()),
             }
         );
         pass.set_pipeline(&self.pipeline);
         for (e, bgs) in bind_groups.iter().enumerate() {
-            pass.set_bind_group(e as u32, bgs, &[]);
+            pass.set_bind_group(e as u32, bgs, dynamic_offsets_of(dynamic_offsets, e));
         }
         pass.dispatch_workgroups(x, y, z)
     }
@@ -99,18 +114,20 @@ impl ComputeObject {
     pub fn create_compute_pass<'a>(
                     &'a self,
                     bind_groups: &'a Vec<wgpu::BindGroup>,
+                    dynamic_offsets: &[&[u32]],
                     encoder: &'a mut wgpu::CommandEncoder,
-                    label: wgpu::Label) -> wgpu::ComputePass<'a> {
+                    label: wgpu::Label,
+                    scope: Option<&GpuScope>) -> wgpu::ComputePass<'a> {
 
             let mut pass = encoder.begin_compute_pass(
                 &wgpu::ComputePassDescriptor {
                     label,
-                    timestamp_writes: None,
+                    timestamp_writes: scope.and_then(|s| s.compute_timestamp_writes()),
                 }
             );
             pass.set_pipeline(&self.pipeline);
             for (e, bgs) in bind_groups.iter().enumerate() {
-                pass.set_bind_group(e as u32, bgs, &[]);
+                pass.set_bind_group(e as u32, bgs, dynamic_offsets_of(dynamic_offsets, e));
             }
             pass
     }
@@ -132,44 +149,48 @@ impl ComputeObject {
     pub fn dispatch_push_constants<T: Pod> (
                     &self,
                     bind_groups: &Vec<wgpu::BindGroup>,
+                    dynamic_offsets: &[&[u32]],
                     encoder: &mut wgpu::CommandEncoder,
                     x: u32,
                     y: u32,
                     z: u32,
                     push_constant_offset: u32,
                     push_constant_data: T,
-                    label: wgpu::Label) {
+                    label: wgpu::Label,
+                    scope: Option<&GpuScope>) {
 
         let mut pass = encoder.begin_compute_pass(
                 &wgpu::ComputePassDescriptor {
                     label,
-                    timestamp_writes: None,
+                    timestamp_writes: scope.and_then(|s| s.compute_timestamp_writes()),
                 }
         );
         pass.set_pipeline(&self.pipeline);
         pass.set_push_constants(push_constant_offset, bytemuck::cast_slice(&[push_constant_data]));
         for (e, bgs) in bind_groups.iter().enumerate() {
-            pass.set_bind_group(e as u32, bgs, &[]);
+            pass.set_bind_group(e as u32, bgs, dynamic_offsets_of(dynamic_offsets, e));
         }
         pass.dispatch_workgroups(x, y, z);
     }
 
     pub fn dispatch_indirect(&self,
                              bind_groups: &Vec<wgpu::BindGroup>,
+                             dynamic_offsets: &[&[u32]],
                              encoder: &mut wgpu::CommandEncoder,
                              indirect_buffer: &wgpu::Buffer,
                              offset: wgpu::BufferAddress,
-                             label: wgpu::Label) {
+                             label: wgpu::Label,
+                             scope: Option<&GpuScope>) {
 
         let mut pass = encoder.begin_compute_pass(
                 &wgpu::ComputePassDescriptor {
                     label,
-                    timestamp_writes: None,
+                    timestamp_writes: scope.and_then(|s| s.compute_timestamp_writes()),
                 }
         );
         pass.set_pipeline(&self.pipeline);
         for (e, bgs) in bind_groups.iter().enumerate() {
-            pass.set_bind_group(e as u32, bgs, &[]);
+            pass.set_bind_group(e as u32, bgs, dynamic_offsets_of(dynamic_offsets, e));
         }
         pass.dispatch_workgroups_indirect(indirect_buffer, offset);
     }
@@ -182,6 +203,9 @@ pub struct RenderObject {
 }
 
 impl RenderObject {
+
+    /// Create a render object with the default preset of RenderPipelineBuilder and one
+    /// per-vertex buffer.
     pub fn init(device: &wgpu::Device,
                 sc_desc: &wgpu::SurfaceConfiguration,
                 wgsl_module: &wgpu::ShaderModule,
@@ -192,91 +216,233 @@ impl RenderObject {
                 topology: wgpu::PrimitiveTopology,
                 ) -> Self {
 
-        let bind_group_layouts = create_bind_group_layouts(device, bind_group_layout_entries);
+        RenderPipelineBuilder::new(sc_desc.format)
+            .with_vertex_buffer(vertex_attributes, wgpu::VertexStepMode::Vertex)
+            .with_front_face(if ccw { wgpu::FrontFace::Ccw } else { wgpu::FrontFace::Cw })
+            .with_topology(topology)
+            .build(device, wgsl_module, bind_group_layout_entries, label)
+    }
+}
 
-        let (stride, attributes) =  create_vb_descriptor(
-            vertex_attributes
-        );
+/// Configures and creates the render pipeline of a RenderObject.
+///
+/// The default preset: entry points vs_main and fs_main, no vertex buffers, triangle list,
+/// counter-clockwise front face, no culling, filled polygons, one color target without
+/// blending and with ColorWrites::COLOR, Depth32Float depth test with Less, sample count 1
+/// and no push constants.
+#[derive(Clone, Debug)]
+pub struct RenderPipelineBuilder {
+    vertex_entry: String,
+    fragment_entry: Option<String>,
+    vertex_buffers: Vec<(Vec<wgpu::VertexFormat>, wgpu::VertexStepMode)>,
+    topology: wgpu::PrimitiveTopology,
+    strip_index_format: Option<wgpu::IndexFormat>,
+    front_face: wgpu::FrontFace,
+    cull_mode: Option<wgpu::Face>,
+    polygon_mode: wgpu::PolygonMode,
+    color_targets: Vec<wgpu::ColorTargetState>,
+    depth_stencil: Option<wgpu::DepthStencilState>,
+    sample_count: u32,
+    push_constant_ranges: Vec<wgpu::PushConstantRange>,
+}
+
+impl RenderPipelineBuilder {
+
+    /// The default preset with one color target of the given format.
+    pub fn new(color_format: wgpu::TextureFormat) -> Self {
+        Self {
+            vertex_entry: "vs_main".to_string(),
+            fragment_entry: Some("fs_main".to_string()),
+            vertex_buffers: Vec::new(),
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            color_targets: vec![wgpu::ColorTargetState {
+                format: color_format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::COLOR,
+            }],
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            sample_count: 1,
+            push_constant_ranges: Vec::new(),
+        }
+    }
+
+    pub fn with_vertex_entry(mut self, entry_point: &str) -> Self {
+        self.vertex_entry = entry_point.to_string();
+        self
+    }
+
+    /// The fragment shader entry point. None creates a pipeline without a fragment stage
+    /// (e.g. for depth only passes).
+    pub fn with_fragment_entry(mut self, entry_point: Option<&str>) -> Self {
+        self.fragment_entry = entry_point.map(|e| e.to_string());
+        self
+    }
+
+    /// Add a vertex buffer. The shader locations continue from the previous buffer.
+    pub fn with_vertex_buffer(mut self, formats: &[wgpu::VertexFormat], step_mode: wgpu::VertexStepMode) -> Self {
+        self.vertex_buffers.push((formats.to_vec(), step_mode));
+        self
+    }
+
+    pub fn with_topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// The index format of indexed strip topologies.
+    pub fn with_strip_index_format(mut self, format: Option<wgpu::IndexFormat>) -> Self {
+        self.strip_index_format = format;
+        self
+    }
+
+    pub fn with_front_face(mut self, front_face: wgpu::FrontFace) -> Self {
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn with_cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    /// Line and Point need wgpu::Features::POLYGON_MODE_LINE and POLYGON_MODE_POINT.
+    pub fn with_polygon_mode(mut self, polygon_mode: wgpu::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    /// Replace the color targets.
+    pub fn with_color_targets(mut self, color_targets: &[wgpu::ColorTargetState]) -> Self {
+        self.color_targets = color_targets.to_vec();
+        self
+    }
+
+    /// Set the blend state of all color targets.
+    pub fn with_blend(mut self, blend: Option<wgpu::BlendState>) -> Self {
+        for target in self.color_targets.iter_mut() {
+            target.blend = blend;
+        }
+        self
+    }
+
+    /// Set the write mask of all color targets.
+    pub fn with_write_mask(mut self, write_mask: wgpu::ColorWrites) -> Self {
+        for target in self.color_targets.iter_mut() {
+            target.write_mask = write_mask;
+        }
+        self
+    }
+
+    /// The depth/stencil state or None for a pipeline without a depth attachment.
+    pub fn with_depth_stencil(mut self, depth_stencil: Option<wgpu::DepthStencilState>) -> Self {
+        self.depth_stencil = depth_stencil;
+        self
+    }
+
+    /// A depth test without stencil.
+    pub fn with_depth(self, format: wgpu::TextureFormat, depth_compare: wgpu::CompareFunction, depth_write_enabled: bool) -> Self {
+        self.with_depth_stencil(Some(wgpu::DepthStencilState {
+            format,
+            depth_write_enabled,
+            depth_compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }))
+    }
+
+    /// The MSAA sample count. The attachments must have the same sample count.
+    pub fn with_sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    /// Push constants need wgpu::Features::PUSH_CONSTANTS.
+    pub fn with_push_constant_ranges(mut self, push_constant_ranges: &[wgpu::PushConstantRange]) -> Self {
+        self.push_constant_ranges = push_constant_ranges.to_vec();
+        self
+    }
+
+    /// Create the bind group layouts and the pipeline.
+    pub fn build(&self,
+                 device: &wgpu::Device,
+                 wgsl_module: &wgpu::ShaderModule,
+                 bind_group_layout_entries: &Vec<Vec<wgpu::BindGroupLayoutEntry>>,
+                 label: wgpu::Label) -> RenderObject {
+
+        let bind_group_layouts = create_bind_group_layouts(device, bind_group_layout_entries);
 
         // Create pipeline layout.
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label,
-            bind_group_layouts: &bind_group_layouts.iter().collect::<Vec<_>>(), // &[&bind_group_layout],
-            push_constant_ranges: &[],
+            bind_group_layouts: &bind_group_layouts.iter().collect::<Vec<_>>(),
+            push_constant_ranges: &self.push_constant_ranges,
         });
 
+        // The shader locations of the attributes continue over the vertex buffers.
+        let mut location = 0;
+        let vertex_buffers: Vec<(u64, Vec<wgpu::VertexAttribute>, wgpu::VertexStepMode)> =
+            self.vertex_buffers.iter().map(|(formats, step_mode)| {
+                let (stride, mut attributes) = create_vb_descriptor(formats);
+                for a in attributes.iter_mut() {
+                    a.shader_location += location;
+                }
+                location += attributes.len() as u32;
+                (stride, attributes, *step_mode)
+            }).collect();
+
+        let buffers: Vec<wgpu::VertexBufferLayout> = vertex_buffers.iter().map(|(stride, attributes, step_mode)| {
+            wgpu::VertexBufferLayout {
+                array_stride: *stride,
+                step_mode: *step_mode,
+                attributes,
+            }
+        }).collect();
+
+        let targets: Vec<Option<wgpu::ColorTargetState>> = self.color_targets.iter().cloned().map(Some).collect();
+
         // Create the render pipeline.
         let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
+            label,
             layout: Some(&pipeline_layout),
             vertex: wgpu::VertexState {
                 module: wgsl_module,
-                entry_point: "vs_main",
-                buffers: &[
-                    wgpu::VertexBufferLayout {
-                        array_stride: stride,
-                        step_mode: wgpu::VertexStepMode::Vertex,
-                        attributes: &attributes,
-                    }],
+                entry_point: &self.vertex_entry,
+                buffers: &buffers,
             },
             primitive: wgpu::PrimitiveState {
-                //topology: wgpu::PrimitiveTopology::TriangleList,
-                topology,
-                strip_index_format: None,
-                front_face: if ccw { wgpu::FrontFace::Ccw } else { wgpu::FrontFace::Cw },
-                cull_mode: None, //Some(wgpu::Face::Back),
-                // cull_mode: Some(wgpu::Face::Front),
-                unclipped_depth: false, // ???
-                polygon_mode: wgpu::PolygonMode::Fill,
+                topology: self.topology,
+                strip_index_format: self.strip_index_format,
+                front_face: self.front_face,
+                cull_mode: self.cull_mode,
+                unclipped_depth: false,
+                polygon_mode: self.polygon_mode,
                 conservative: false,
             },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth32Float,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState {
-                    front: wgpu::StencilFaceState::IGNORE,
-                    back: wgpu::StencilFaceState::IGNORE,
-                    read_mask: 0,
-                    write_mask: 0,
-                },
-                bias: wgpu::DepthBiasState {
-                    constant: 0,
-                    slope_scale: 0.0,
-                    clamp: 0.0,
-                },
-            }),
+            depth_stencil: self.depth_stencil.clone(),
             multisample: wgpu::MultisampleState {
-                count: 1,
+                count: self.sample_count,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
-            fragment: Some(wgpu::FragmentState {
+            fragment: self.fragment_entry.as_ref().map(|entry_point| wgpu::FragmentState {
                 module: wgsl_module,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: sc_desc.format,
-                    blend: None, //Some(wgpu::BlendState {
-                           //     color: wgpu::BlendComponent {
-                           //          src_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                           //          dst_factor: wgpu::BlendFactor::OneMinusDstAlpha,
-                           //          operation: wgpu::BlendOperation::Max,
-                           //     },
-                           //     alpha: wgpu::BlendComponent {
-                           //          src_factor: wgpu::BlendFactor::SrcAlpha,
-                           //          dst_factor: wgpu::BlendFactor::One,
-                           //          operation: wgpu::BlendOperation::Add,
-                           //     },
-                           // }),
-                    // alpha_blend: wgpu::BlendState::REPLACE,
-                    // color_blend: wgpu::BlendState::REPLACE,
-                    write_mask: wgpu::ColorWrites::COLOR,
-                })],
+                entry_point,
+                targets: &targets,
             }),
             multiview: None,
         });
 
-        Self {
+        RenderObject {
             bind_group_layouts,
             pipeline,
             bind_group_layout_entries: bind_group_layout_entries.to_vec(),
@@ -284,6 +450,12 @@ impl RenderObject {
     }
 }
 
+/// The dynamic offsets of the bind group at index. dynamic_offsets has one slice per bind
+/// group (e.g. offsets from UniformRing::push). Missing slices mean no dynamic offsets.
+fn dynamic_offsets_of<'a>(dynamic_offsets: &[&'a [u32]], index: usize) -> &'a [u32] {
+    dynamic_offsets.get(index).copied().unwrap_or(&[])
+}
+
 /// Takes wgpu::VertexFormats as input and returns (stride, Vec<wgpu::VertexBufferDescriptor>)
 pub fn create_vb_descriptor(formats: &Vec<wgpu::VertexFormat>) -> (u64, Vec<wgpu::VertexAttribute>) { 
 
@@ -408,122 +580,354 @@ pub fn create_bind_group_layouts(device: &wgpu::Device, layout_entries: &Vec<Vec
 //++ 
 //++ }
 
-// TODO: optional depth-texture.
+/// Configures the attachments of a render pass.
+///
+/// let mut render_pass = RenderPassBuilder::new()
+///     .with_color(view, wgpu::LoadOp::Clear(wgpu::Color::BLACK), StoreOp::Store)
+///     .with_depth(&depth_texture.view, wgpu::LoadOp::Clear(1.0), StoreOp::Store)
+///     .begin(&mut encoder);
+#[derive(Default)]
+pub struct RenderPassBuilder<'a> {
+    label: Option<&'a str>,
+    color_attachments: Vec<Option<wgpu::RenderPassColorAttachment<'a>>>,
+    depth_stencil_attachment: Option<wgpu::RenderPassDepthStencilAttachment<'a>>,
+    occlusion_query_set: Option<&'a wgpu::QuerySet>,
+    timestamp_writes: Option<wgpu::RenderPassTimestampWrites<'a>>,
+}
+
+impl<'a> RenderPassBuilder<'a> {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_label(mut self, label: &'a str) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    /// Add a color attachment.
+    pub fn with_color(self, view: &'a wgpu::TextureView, load: wgpu::LoadOp<wgpu::Color>, store: StoreOp) -> Self {
+        self.with_color_attachment(Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations { load, store },
+        }))
+    }
+
+    /// Add a multisampled color attachment that is resolved to resolve_target.
+    pub fn with_color_resolve(self,
+                              view: &'a wgpu::TextureView,
+                              resolve_target: &'a wgpu::TextureView,
+                              load: wgpu::LoadOp<wgpu::Color>,
+                              store: StoreOp) -> Self {
+        self.with_color_attachment(Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: Some(resolve_target),
+            ops: wgpu::Operations { load, store },
+        }))
+    }
+
+    /// Add a color attachment. None leaves the location unused.
+    pub fn with_color_attachment(mut self, attachment: Option<wgpu::RenderPassColorAttachment<'a>>) -> Self {
+        self.color_attachments.push(attachment);
+        self
+    }
+
+    /// A depth attachment without stencil.
+    pub fn with_depth(self, view: &'a wgpu::TextureView, load: wgpu::LoadOp<f32>, store: StoreOp) -> Self {
+        self.with_depth_stencil(Some(wgpu::RenderPassDepthStencilAttachment {
+            view,
+            depth_ops: Some(wgpu::Operations { load, store }),
+            stencil_ops: None,
+        }))
+    }
+
+    /// The depth/stencil attachment or None.
+    pub fn with_depth_stencil(mut self, attachment: Option<wgpu::RenderPassDepthStencilAttachment<'a>>) -> Self {
+        self.depth_stencil_attachment = attachment;
+        self
+    }
+
+    pub fn with_occlusion_query_set(mut self, query_set: Option<&'a wgpu::QuerySet>) -> Self {
+        self.occlusion_query_set = query_set;
+        self
+    }
+
+    pub fn with_timestamp_writes(mut self, timestamp_writes: Option<wgpu::RenderPassTimestampWrites<'a>>) -> Self {
+        self.timestamp_writes = timestamp_writes;
+        self
+    }
+
+    /// The timestamp writes of a GpuProfiler scope.
+    pub fn with_scope(self, scope: Option<&GpuScope<'a>>) -> Self {
+        self.with_timestamp_writes(scope.and_then(|s| s.render_timestamp_writes()))
+    }
+
+    /// Begin the render pass.
+    pub fn begin<'e>(self, encoder: &'e mut wgpu::CommandEncoder) -> wgpu::RenderPass<'e> where 'a: 'e {
+        encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: self.label,
+                color_attachments: &self.color_attachments,
+                depth_stencil_attachment: self.depth_stencil_attachment,
+                timestamp_writes: self.timestamp_writes,
+                occlusion_query_set: self.occlusion_query_set,
+        })
+    }
+}
+
+/// Create a render pass with one color attachment and an optional depth attachment. If clear
+/// is true both are cleared (the color to clear_color or black).
 pub fn create_render_pass<'a>(encoder: &'a mut wgpu::CommandEncoder,
                           view: &'a wgpu::TextureView,
-                          depth_texture: &'a Texture,
+                          depth_texture: Option<&'a Texture>,
                           clear: bool,
-                          clear_color: &Option<wgpu::Color>) -> impl wgpu::util::RenderEncoder<'a> {
+                          clear_color: &Option<wgpu::Color>,
+                          scope: Option<&GpuScope<'a>>) -> wgpu::RenderPass<'a> {
+
+    let color_load = match clear {
+        true => wgpu::LoadOp::Clear(clear_color.unwrap_or(wgpu::Color::BLACK)),
+        false => wgpu::LoadOp::Load,
+    };
+    let depth_load = match clear { true => wgpu::LoadOp::Clear(1.0), false => wgpu::LoadOp::Load };
+
+    let mut builder = RenderPassBuilder::new()
+        .with_label("Render pass descriptor")
+        .with_color(view, color_load, StoreOp::Store)
+        .with_scope(scope);
+
+    if let Some(depth_texture) = depth_texture {
+        builder = builder.with_depth(&depth_texture.view, depth_load, StoreOp::Store);
+    }
 
-    let render_pass = encoder.begin_render_pass(
-            &wgpu::RenderPassDescriptor {
-                label: Some("Render pass descriptor"),
-                color_attachments: &[
-                    Some(wgpu::RenderPassColorAttachment {
-                            view,
-                            resolve_target: None,
-                            ops: wgpu::Operations {
-                                load: match clear {
-                                    true => {
-                                        wgpu::LoadOp::Clear(clear_color.unwrap())
-                                    }
-                                    false => {
-                                        wgpu::LoadOp::Load
-                                    }
-                                },
-                                store: StoreOp::Store,
-                            },
-                    }),
-                ],
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &depth_texture.view,
-                depth_ops: Some(wgpu::Operations {
-                        load: match clear { true => wgpu::LoadOp::Clear(1.0), false => wgpu::LoadOp::Load },
-                        store: StoreOp::Store,
-                }),
-                stencil_ops: None,
-                }),
-            timestamp_writes: None,
-            occlusion_query_set: None,
-    });
-
-    render_pass
+    builder.begin(encoder)
 }
 
-pub fn draw_indirect(
-            encoder: &mut wgpu::CommandEncoder,
-            view: &wgpu::TextureView,
-            depth_texture: &Texture,
-            bind_groups: &Vec<wgpu::BindGroup>,
-            pipeline: &wgpu::RenderPipeline,
-            draw_buffer: &wgpu::Buffer,
-            indirect_buffer: &wgpu::Buffer,
-            offset: wgpu::BufferAddress,
-            clear: bool) {
-
-    let mut render_pass = create_render_pass(
-                          encoder,
-                          view,
-                          depth_texture,
-                          clear,
-                          &Some(wgpu::Color {
-                              r: 1.0,
-                              g: 0.0,
-                              b: 0.0,
-                              a: 1.0,
-                          })
-    );
-    
+/// Set the pipeline, the bind groups and the vertex buffers. vertex_buffers[i] is bound to
+/// slot i.
+fn set_draw_state<'a>(render_pass: &mut wgpu::RenderPass<'a>,
+                      bind_groups: &'a [wgpu::BindGroup],
+                      dynamic_offsets: &[&[u32]],
+                      pipeline: &'a wgpu::RenderPipeline,
+                      vertex_buffers: &[&'a wgpu::Buffer]) {
+
     render_pass.set_pipeline(pipeline);
 
     // Set bind groups.
     for (e, bgs) in bind_groups.iter().enumerate() {
-        render_pass.set_bind_group(e as u32, bgs, &[]);
-    }
-    
-    // Set vertex buffer.
-    render_pass.set_vertex_buffer(
-        0,
-        draw_buffer.slice(..)
-    );
-    
+        render_pass.set_bind_group(e as u32, bgs, dynamic_offsets_of(dynamic_offsets, e));
+    }
+
+    // Set vertex buffers.
+    for (slot, buffer) in vertex_buffers.iter().enumerate() {
+        render_pass.set_vertex_buffer(slot as u32, buffer.slice(..));
+    }
+}
+
+pub fn draw_indirect<'a>(
+            render_pass: &mut wgpu::RenderPass<'a>,
+            bind_groups: &'a [wgpu::BindGroup],
+            dynamic_offsets: &[&[u32]],
+            pipeline: &'a wgpu::RenderPipeline,
+            draw_buffer: &'a wgpu::Buffer,
+            indirect_buffer: &'a wgpu::Buffer,
+            offset: wgpu::BufferAddress) {
+
+    set_draw_state(render_pass, bind_groups, dynamic_offsets, pipeline, &[draw_buffer]);
     render_pass.draw_indirect(indirect_buffer, offset);
 }
 
-pub fn draw(encoder: &mut wgpu::CommandEncoder,
-            view: &wgpu::TextureView,
-            depth_texture: &Texture,
-            bind_groups: &[wgpu::BindGroup],
-            pipeline: &wgpu::RenderPipeline,
-            draw_buffer: &wgpu::Buffer,
-            range: Range<u32>,
-            clear: bool) {
-
-    let mut render_pass = create_render_pass(
-                          encoder,
-                          view,
-                          depth_texture,
-                          clear,
-                          &Some(wgpu::Color {
-                              r: 1.0,
-                              g: 0.0,
-                              b: 0.0,
-                              a: 1.0,
-                          })
-    );
-    
-    render_pass.set_pipeline(pipeline);
+pub fn draw<'a>(render_pass: &mut wgpu::RenderPass<'a>,
+            bind_groups: &'a [wgpu::BindGroup],
+            dynamic_offsets: &[&[u32]],
+            pipeline: &'a wgpu::RenderPipeline,
+            draw_buffer: &'a wgpu::Buffer,
+            range: Range<u32>) {
 
-    // Set bind groups.
-    for (e, bgs) in bind_groups.iter().enumerate() {
-        render_pass.set_bind_group(e as u32, bgs, &[]);
-    }
-    
-    // Set vertex buffer.
-    render_pass.set_vertex_buffer(
-        0,
-        draw_buffer.slice(..)
-    );
-    
+    set_draw_state(render_pass, bind_groups, dynamic_offsets, pipeline, &[draw_buffer]);
     render_pass.draw(range, 0..1);
 }
+
+/// Draw the instances of the vertices. The per-instance data is in instance_buffer (slot 1,
+/// see RenderPipelineBuilder::with_vertex_buffer with VertexStepMode::Instance).
+pub fn draw_instanced<'a>(render_pass: &mut wgpu::RenderPass<'a>,
+            bind_groups: &'a [wgpu::BindGroup],
+            dynamic_offsets: &[&[u32]],
+            pipeline: &'a wgpu::RenderPipeline,
+            draw_buffer: &'a wgpu::Buffer,
+            instance_buffer: &'a wgpu::Buffer,
+            vertices: Range<u32>,
+            instances: Range<u32>) {
+
+    set_draw_state(render_pass, bind_groups, dynamic_offsets, pipeline, &[draw_buffer, instance_buffer]);
+    render_pass.draw(vertices, instances);
+}
+
+/// Draw with an index buffer (IndexFormat::Uint16 or Uint32). An optional instance buffer is
+/// bound to slot 1. base_vertex is added to each index.
+pub fn draw_indexed<'a>(render_pass: &mut wgpu::RenderPass<'a>,
+            bind_groups: &'a [wgpu::BindGroup],
+            dynamic_offsets: &[&[u32]],
+            pipeline: &'a wgpu::RenderPipeline,
+            draw_buffer: &'a wgpu::Buffer,
+            instance_buffer: Option<&'a wgpu::Buffer>,
+            index_buffer: &'a wgpu::Buffer,
+            index_format: wgpu::IndexFormat,
+            indices: Range<u32>,
+            base_vertex: i32,
+            instances: Range<u32>) {
+
+    let vertex_buffers: Vec<&wgpu::Buffer> = std::iter::once(draw_buffer).chain(instance_buffer).collect();
+    set_draw_state(render_pass, bind_groups, dynamic_offsets, pipeline, &vertex_buffers);
+    render_pass.set_index_buffer(index_buffer.slice(..), index_format);
+    render_pass.draw_indexed(indices, base_vertex, instances);
+}
+
+/// Draw with an index buffer and the DrawIndexedIndirect arguments at offset of the
+/// indirect buffer. An optional instance buffer is bound to slot 1.
+pub fn draw_indexed_indirect<'a>(render_pass: &mut wgpu::RenderPass<'a>,
+            bind_groups: &'a [wgpu::BindGroup],
+            dynamic_offsets: &[&[u32]],
+            pipeline: &'a wgpu::RenderPipeline,
+            draw_buffer: &'a wgpu::Buffer,
+            instance_buffer: Option<&'a wgpu::Buffer>,
+            index_buffer: &'a wgpu::Buffer,
+            index_format: wgpu::IndexFormat,
+            indirect_buffer: &'a wgpu::Buffer,
+            offset: wgpu::BufferAddress) {
+
+    let vertex_buffers: Vec<&wgpu::Buffer> = std::iter::once(draw_buffer).chain(instance_buffer).collect();
+    set_draw_state(render_pass, bind_groups, dynamic_offsets, pipeline, &vertex_buffers);
+    render_pass.set_index_buffer(index_buffer.slice(..), index_format);
+    render_pass.draw_indexed_indirect(indirect_buffer, offset);
+}
+
+/// How multi_draw_indirect issues the commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MultiDrawPath {
+    /// multi_draw_*indirect_count with the count from a gpu buffer.
+    Count,
+    /// multi_draw_*indirect with max_count commands.
+    Multi,
+    /// A loop of max_count draw_*indirect calls.
+    Loop,
+}
+
+fn multi_draw_path(features: wgpu::Features, has_count_buffer: bool) -> MultiDrawPath {
+    if has_count_buffer && features.contains(wgpu::Features::MULTI_DRAW_INDIRECT_COUNT) {
+        MultiDrawPath::Count
+    }
+    else if features.contains(wgpu::Features::MULTI_DRAW_INDIRECT) {
+        MultiDrawPath::Multi
+    }
+    else {
+        MultiDrawPath::Loop
+    }
+}
+
+/// Warns once that the gpu generated draw count is ignored.
+static IGNORED_COUNT_WARNING: Once = Once::new();
+
+/// Issue max_count indirect draw commands (DrawIndirect or DrawIndexedIndirect if indexed)
+/// starting from offset of the indirect buffer.
+fn multi_draw_commands<'a>(render_pass: &mut wgpu::RenderPass<'a>,
+                           features: wgpu::Features,
+                           indexed: bool,
+                           indirect_buffer: &'a wgpu::Buffer,
+                           offset: wgpu::BufferAddress,
+                           count_buffer: Option<(&'a wgpu::Buffer, wgpu::BufferAddress)>,
+                           max_count: u32) {
+
+    let path = multi_draw_path(features, count_buffer.is_some());
+
+    if count_buffer.is_some() && path != MultiDrawPath::Count {
+        IGNORED_COUNT_WARNING.call_once(|| log::warn!(
+            "MULTI_DRAW_INDIRECT_COUNT isn't enabled. The count buffer is ignored and all max_count \
+             indirect commands are drawn, the unused commands must have instance_count 0."));
+    }
+
+    match (path, count_buffer) {
+        (MultiDrawPath::Count, Some((count_buffer, count_offset))) => {
+            if indexed { render_pass.multi_draw_indexed_indirect_count(indirect_buffer, offset, count_buffer, count_offset, max_count); }
+            else       { render_pass.multi_draw_indirect_count(indirect_buffer, offset, count_buffer, count_offset, max_count); }
+        }
+        (MultiDrawPath::Multi, _) => {
+            if indexed { render_pass.multi_draw_indexed_indirect(indirect_buffer, offset, max_count); }
+            else       { render_pass.multi_draw_indirect(indirect_buffer, offset, max_count); }
+        }
+        _ => {
+            let stride = (if indexed { size_of::<DrawIndexedIndirect>() } else { size_of::<DrawIndirect>() }) as wgpu::BufferAddress;
+            for i in 0..max_count as wgpu::BufferAddress {
+                if indexed { render_pass.draw_indexed_indirect(indirect_buffer, offset + i * stride); }
+                else       { render_pass.draw_indirect(indirect_buffer, offset + i * stride); }
+            }
+        }
+    }
+}
+
+/// Draw max_count DrawIndirect commands from the indirect buffer with one call.
+///
+/// Uses multi_draw_indirect_count if count_buffer (buffer, offset of a u32) is given and
+/// wgpu::Features::MULTI_DRAW_INDIRECT_COUNT is enabled, multi_draw_indirect if
+/// MULTI_DRAW_INDIRECT is enabled and otherwise a loop of draw_indirect calls (e.g. on the
+/// software adapter).
+///
+/// Without MULTI_DRAW_INDIRECT_COUNT the count buffer is ignored and all max_count commands
+/// are drawn, so the commands past the count must have instance_count 0 (e.g. clear the
+/// indirect buffer before culling). A warning is logged once if a count buffer is ignored.
+pub fn multi_draw_indirect<'a>(device: &wgpu::Device,
+            render_pass: &mut wgpu::RenderPass<'a>,
+            bind_groups: &'a [wgpu::BindGroup],
+            dynamic_offsets: &[&[u32]],
+            pipeline: &'a wgpu::RenderPipeline,
+            draw_buffer: &'a wgpu::Buffer,
+            indirect_buffer: &'a wgpu::Buffer,
+            offset: wgpu::BufferAddress,
+            count_buffer: Option<(&'a wgpu::Buffer, wgpu::BufferAddress)>,
+            max_count: u32) {
+
+    set_draw_state(render_pass, bind_groups, dynamic_offsets, pipeline, &[draw_buffer]);
+    multi_draw_commands(render_pass, device.features(), false, indirect_buffer, offset, count_buffer, max_count);
+}
+
+/// Draw max_count DrawIndexedIndirect commands from the indirect buffer with one call. See
+/// multi_draw_indirect for the fallbacks. An optional instance buffer is bound to slot 1.
+///
+/// Without MULTI_DRAW_INDIRECT_COUNT the count buffer is ignored and all max_count commands
+/// are drawn, so the commands past the count must have instance_count 0.
+pub fn multi_draw_indexed_indirect<'a>(device: &wgpu::Device,
+            render_pass: &mut wgpu::RenderPass<'a>,
+            bind_groups: &'a [wgpu::BindGroup],
+            dynamic_offsets: &[&[u32]],
+            pipeline: &'a wgpu::RenderPipeline,
+            draw_buffer: &'a wgpu::Buffer,
+            instance_buffer: Option<&'a wgpu::Buffer>,
+            index_buffer: &'a wgpu::Buffer,
+            index_format: wgpu::IndexFormat,
+            indirect_buffer: &'a wgpu::Buffer,
+            offset: wgpu::BufferAddress,
+            count_buffer: Option<(&'a wgpu::Buffer, wgpu::BufferAddress)>,
+            max_count: u32) {
+
+    let vertex_buffers: Vec<&wgpu::Buffer> = std::iter::once(draw_buffer).chain(instance_buffer).collect();
+    set_draw_state(render_pass, bind_groups, dynamic_offsets, pipeline, &vertex_buffers);
+    render_pass.set_index_buffer(index_buffer.slice(..), index_format);
+    multi_draw_commands(render_pass, device.features(), true, indirect_buffer, offset, count_buffer, max_count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multi_draw_fallbacks() {
+        let all = wgpu::Features::MULTI_DRAW_INDIRECT | wgpu::Features::MULTI_DRAW_INDIRECT_COUNT;
+        assert_eq!(multi_draw_path(all, true), MultiDrawPath::Count);
+        assert_eq!(multi_draw_path(all, false), MultiDrawPath::Multi);
+        assert_eq!(multi_draw_path(wgpu::Features::MULTI_DRAW_INDIRECT, true), MultiDrawPath::Multi);
+        assert_eq!(multi_draw_path(wgpu::Features::empty(), true), MultiDrawPath::Loop);
+        assert_eq!(size_of::<DrawIndirect>(), 16);
+        assert_eq!(size_of::<DrawIndexedIndirect>(), 20);
+    }
+}
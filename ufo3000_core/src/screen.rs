@@ -1,14 +1,20 @@
 use std::mem;
 use crate::texture::Texture;
+use crate::template::RenderTarget;
 #[cfg(target_arch = "wasm32")]
 use crate::template::OffscreenCanvasSetup;
 
-/// A struct that owns the current wgpu::SurfaceTexture and the optional depth texture.
+/// A struct that owns the current wgpu::SurfaceTexture and the optional depth texture. The
+/// depth texture is recreated automatically when the size of sc_desc changes.
 /// TODO: getter_functions for attributes
 pub struct ScreenTexture {
     pub surface_texture: Option<wgpu::SurfaceTexture>,
+    /// The view of the current frame. Available after acquire_screen_texture both for surface
+    /// and offscreen targets.
+    pub view: Option<wgpu::TextureView>,
     #[allow(dead_code)]
     pub depth_texture: Option<Texture>,
+    out_of_memory: bool,
 }
 
 impl ScreenTexture {
@@ -35,39 +41,92 @@ impl ScreenTexture {
 
         Self {
             surface_texture: None,
+            view: None,
             depth_texture,
+            out_of_memory: false,
         }
     }
 
-    /// Acquire the current screen texture.
+    /// Acquire the current screen texture. For offscreen targets only the view is created.
+    /// Returns false if the frame should be skipped (the surface timed out, couldn't be
+    /// reconfigured or ran out of memory). In that case prepare_for_rendering must not be called.
+    /// After OutOfMemory the application should exit (see out_of_memory).
+    #[must_use]
     pub fn acquire_screen_texture(
             &mut self,
             device: &wgpu::Device,
             sc_desc: &wgpu::SurfaceConfiguration,
-            surface: &wgpu::Surface) {
+            target: &RenderTarget) -> bool {
+
+        self.update_depth_texture(device, sc_desc);
+
+        let surface = match target {
+            RenderTarget::Surface(surface) => surface,
+            RenderTarget::Offscreen(texture) => {
+                self.view = Some(texture.texture.create_view(&wgpu::TextureViewDescriptor::default()));
+                return true;
+            }
+        };
 
         let frame = match surface.get_current_texture() {
             Ok(frame) => {frame},
             Err(wgpu::SurfaceError::Lost) | Err(wgpu::SurfaceError::Outdated) => {
                 surface.configure(device, sc_desc);
-                
-                surface.get_current_texture().expect("Failed to acquire next texture")
+
+                match surface.get_current_texture() {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        log::warn!("Skipping frame. Couldn't acquire the next frame texture after reconfiguring the surface: {}", e);
+                        return false;
+                    }
+                }
             },
-            Err(wgpu::SurfaceError::Timeout) => panic!("Timeout occurred while acquiring the next frame texture."),
-            Err(wgpu::SurfaceError::OutOfMemory) => panic!("OutOfMemory occurred while acquiring the next frame texture."),
+            Err(wgpu::SurfaceError::Timeout) => {
+                log::warn!("Skipping frame. Timeout occurred while acquiring the next frame texture.");
+                return false;
+            }
+            Err(wgpu::SurfaceError::OutOfMemory) => {
+                log::error!("Skipping frame. OutOfMemory occurred while acquiring the next frame texture.");
+                self.out_of_memory = true;
+                return false;
+            }
         };
+        self.view = Some(frame.texture.create_view(&wgpu::TextureViewDescriptor::default()));
         self.surface_texture = Some(frame);
+        true
+    }
+
+    /// True if acquiring a frame has failed with OutOfMemory. The surface can't be used anymore,
+    /// return true from Application::should_exit.
+    pub fn out_of_memory(&self) -> bool {
+        self.out_of_memory
+    }
+
+    /// Recreate the depth texture if its size doesn't match sc_desc.
+    fn update_depth_texture(&mut self, device: &wgpu::Device, sc_desc: &wgpu::SurfaceConfiguration) {
+        let outdated = match &self.depth_texture {
+            Some(depth_texture) => depth_texture.width() != sc_desc.width || depth_texture.height() != sc_desc.height,
+            None => false,
+        };
+        if outdated {
+            self.depth_texture = Some(Texture::create_depth_texture(device, sc_desc, Some("depth_texture")));
+        }
     }
 
     /// This must be called so the texture can be actually rendered to the screen. Call this method
     /// after wgpu::Queue::submit.
+    /// For offscreen targets there is nothing to present.
     #[cfg(not(target_arch = "wasm32"))]
     pub fn prepare_for_rendering(&mut self) {
-        if self.surface_texture.is_none() {
-            panic!("ScreenTexture doesn't have a surface_texture. Consider calling the ScreenTexture::acquire_screen_texture before this method.");
+        if self.view.is_none() {
+            panic!("ScreenTexture doesn't have a view. Consider calling the ScreenTexture::acquire_screen_texture before this method.");
         }
 
-        mem::take(&mut self.surface_texture).unwrap().present();
+        self.view = None;
+
+        if let Some(surface_texture) = mem::take(&mut self.surface_texture) {
+            surface_texture.present();
+        }
     }
 
     #[cfg(target_arch = "wasm32")]
@@ -77,6 +136,8 @@ impl ScreenTexture {
             panic!("ScreenTexture doesn't have a surface_texture. Consider calling the ScreenTexture::acquire_screen_texture before this method.");
         }
 
+        self.view = None;
+
         mem::take(&mut self.surface_texture).unwrap().present();
 
         #[cfg(target_arch = "wasm32")]
@@ -1,4 +1,7 @@
+use std::fmt;
 use std::future::Future;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
 
 #[cfg(target_arch = "wasm32")]
 use {
@@ -13,13 +16,19 @@ use {
 // use log::LevelFilter;
 
 use winit::{
-    event::{Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
+    event::{Event, StartCause, WindowEvent},
+    event_loop::{ControlFlow, EventLoop, EventLoopProxy},
 };
 
 pub use winit::event::VirtualKeyCode as Key;
 
 use crate::input::InputCache;
+use crate::frame_stats::{FramePhase, FrameStats};
+use crate::app_config::{AppConfig, RedrawMode};
+use crate::fixed_step::{FixedStepClock, FixedStepKeys};
+use crate::texture::Texture;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::image::{write_png_rgba8, swap_red_blue};
 
 /// A trait for wgpu-rs based application.
 pub trait Application: Sized + 'static {
@@ -31,7 +40,7 @@ pub trait Application: Sized + 'static {
     fn render(&mut self,
               device: &wgpu::Device,
               queue: &mut wgpu::Queue,
-              surface: &wgpu::Surface,
+              target: &RenderTarget,
               sc_desc: &wgpu::SurfaceConfiguration,
               #[cfg(target_arch = "wasm32")]
               offscreen_canvas_setup: &OffscreenCanvasSetup,
@@ -46,10 +55,56 @@ pub trait Application: Sized + 'static {
     /// A function for updating the state of the application.
     fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, input: &InputCache, spawner: &Spawner);
 
+    /// A function for advancing the simulation by time_step seconds. FixedStepLoop calls this
+    /// once per tick instead of update. Calls update by default.
+    fn fixed_update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, input: &InputCache, _time_step: f64, spawner: &Spawner) {
+        self.update(device, queue, input, spawner);
+    }
+
+    /// FixedStepLoop calls this before render with the interpolation factor [0, 1) between the
+    /// previous and the current simulation state.
+    fn set_interpolation_alpha(&mut self, _alpha: f64) { }
+
+    /// In the on-demand redraw modes (see RedrawMode) BasicLoop calls this after update. Return
+    /// true if the application needs a redraw, for example when the camera moved.
+    fn needs_redraw(&mut self, _input: &InputCache) -> bool {
+        false
+    }
+
+    /// Decides whether the loop should exit. Called once per frame and when the window is
+    /// closed. Return false to veto the request or true to exit without a request. By default
+    /// the application exits on any request.
+    fn should_exit(&mut self, _input: &InputCache, request: ExitRequest) -> bool {
+        request != ExitRequest::None
+    }
+
+    /// Called after every rendered frame with the frame timings of BasicLoop and FixedStepLoop.
+    fn frame_finished(&mut self, _stats: &FrameStats) { }
+
     /// A function for program exit event.
     fn exit(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, input: &InputCache, spawner: &Spawner);
 }
 
+/// The reason why Application::should_exit is called.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitRequest {
+    /// Nothing requested exit this frame.
+    None,
+    /// The quit key of AppConfig was pressed.
+    QuitKey,
+    /// The window was closed.
+    CloseRequested,
+}
+
+/// The target of Application::render. Use ScreenTexture::acquire_screen_texture to get a view
+/// for both of the cases.
+pub enum RenderTarget<'a> {
+    /// The window surface.
+    Surface(&'a wgpu::Surface),
+    /// An offscreen color texture (see OffscreenLoop).
+    Offscreen(&'a Texture),
+}
+
 /// Canvas for web-platform. 
 #[cfg(target_arch = "wasm32")]
 pub struct OffscreenCanvasSetup {
@@ -68,21 +123,44 @@ pub trait Loop: Sized + 'static {
     fn run<A: Application>(&self, application: A, configuration: WGPUConfiguration);
 }
 
-/// A struct that holds the wgpu-rs application resources.
+/// A struct that holds the wgpu-rs application resources. The window, event_loop and surface
+/// are None for offscreen configurations (see setup_offscreen).
 pub struct WGPUConfiguration {
-    pub window: winit::window::Window,
-    pub event_loop: EventLoop<()>,
+    pub window: Option<winit::window::Window>,
+    pub event_loop: Option<EventLoop<()>>,
     pub instance: wgpu::Instance,
     pub size: winit::dpi::PhysicalSize<u32>,
-    pub surface: wgpu::Surface,
+    pub surface: Option<wgpu::Surface>,
     pub adapter: wgpu::Adapter,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub sc_desc: wgpu::SurfaceConfiguration,
+    pub config: AppConfig,
     #[cfg(target_arch = "wasm32")]
     pub offscreen_canvas_setup: OffscreenCanvasSetup,
 }
 
+impl WGPUConfiguration {
+    /// Create a RedrawRequester for waking up the loop. None if there is no event loop.
+    pub fn redraw_requester(&self) -> Option<RedrawRequester> {
+        self.event_loop.as_ref().map(|event_loop| RedrawRequester { proxy: event_loop.create_proxy() })
+    }
+}
+
+/// Wakes up BasicLoop and requests a redraw in the on-demand redraw modes. Can be cloned and
+/// used from async tasks, for example when a readback has finished.
+#[derive(Clone)]
+pub struct RedrawRequester {
+    proxy: EventLoopProxy<()>,
+}
+
+impl RedrawRequester {
+    pub fn request_redraw(&self) {
+        // Fails only if the loop has already exited.
+        let _ = self.proxy.send_event(());
+    }
+}
+
 /// A trait for configure wgpu-rs engine.
 pub trait WGPUFeatures: Sized + 'static {
     fn optional_features() -> wgpu::Features {
@@ -103,137 +181,571 @@ pub trait WGPUFeatures: Sized + 'static {
     }
 }
 
-/// A "basic" loop.
-pub struct BasicLoop { }
+/// The requirements of a WGPUFeatures implementation as a value. Used for trying fallback
+/// feature sets.
+#[derive(Clone, Debug)]
+pub struct FeatureSet {
+    pub optional_features: wgpu::Features,
+    pub required_features: wgpu::Features,
+    pub required_limits: wgpu::Limits,
+    pub required_downlevel_capabilities: wgpu::DownlevelCapabilities,
+}
 
-impl Loop for BasicLoop {
+impl FeatureSet {
+    /// Create the feature set of P.
+    pub fn of<P: WGPUFeatures>() -> Self {
+        Self {
+            optional_features: P::optional_features(),
+            required_features: P::required_features(),
+            required_limits: P::required_limits(),
+            required_downlevel_capabilities: P::required_downlevel_capabilities(),
+        }
+    }
+}
 
-    fn init() -> Self {
-        BasicLoop {}
+/// Errors that can occur while initializing wgpu-rs.
+#[derive(Debug)]
+pub enum SetupError {
+    /// The window couldn't be created.
+    Window(winit::error::OsError),
+    /// The canvas couldn't be set up (web only).
+    Canvas(&'static str),
+    /// The surface couldn't be created.
+    Surface(wgpu::CreateSurfaceError),
+    /// The adapter can't present to the surface (no formats or alpha modes).
+    UnsupportedSurface,
+    /// WGPU_POWER_PREF has other value than "low" or "high".
+    UnknownPowerPreference(String),
+    /// No suitable adapter was found.
+    NoAdapter,
+    /// The adapter doesn't support these required features.
+    MissingFeatures(wgpu::Features),
+    /// The shader model of the adapter is too low.
+    ShaderModel { required: wgpu::ShaderModel, supported: wgpu::ShaderModel },
+    /// The adapter doesn't support these required downlevel flags.
+    MissingDownlevelFlags(wgpu::DownlevelFlags),
+    /// The device request failed.
+    RequestDevice(wgpu::RequestDeviceError),
+}
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SetupError::Window(e) => write!(f, "Couldn't create the window: {}", e),
+            SetupError::Canvas(e) => write!(f, "Couldn't set up the canvas: {}", e),
+            SetupError::Surface(e) => write!(f, "Couldn't create the surface: {}", e),
+            SetupError::UnsupportedSurface => write!(f, "The surface isn't supported by the adapter"),
+            SetupError::UnknownPowerPreference(p) => write!(f, "Unknown power preference: {}", p),
+            SetupError::NoAdapter => write!(f, "No suitable GPU adapters found on the system"),
+            SetupError::MissingFeatures(features) =>
+                write!(f, "Adapter does not support required features: {:?}", features),
+            SetupError::ShaderModel { required, supported } =>
+                write!(f, "Adapter does not support the minimum shader model {:?} (supported {:?})", required, supported),
+            SetupError::MissingDownlevelFlags(flags) =>
+                write!(f, "Adapter does not support the required downlevel capabilities: {:?}", flags),
+            SetupError::RequestDevice(e) => write!(f, "Unable to request the device: {}", e),
+        }
     }
+}
 
-    fn run<A: Application>(&self, mut application: A, WGPUConfiguration {
-        window,
-        event_loop,
-        instance,
-        mut size,
-        surface,
-        adapter,
-        device,
-        mut queue,
-        mut sc_desc,
-        #[cfg(target_arch = "wasm32")]
-        offscreen_canvas_setup
-        }: WGPUConfiguration,) {
+impl std::error::Error for SetupError {}
+
+/// The state that BasicLoop and FixedStepLoop share. Handles the window events, the resizes,
+/// the minimized window, rendering, polling and exiting.
+struct LoopState<A: Application> {
+    application: A,
+    window: winit::window::Window,
+    // Kept alive for the lifetime of the loop.
+    _instance: wgpu::Instance,
+    _adapter: wgpu::Adapter,
+    size: winit::dpi::PhysicalSize<u32>,
+    surface: wgpu::Surface,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    sc_desc: wgpu::SurfaceConfiguration,
+    config: AppConfig,
+    input: InputCache,
+    frame_stats: FrameStats,
+    #[cfg(not(target_arch = "wasm32"))]
+    spawner: Spawner<'static>,
+    #[cfg(target_arch = "wasm32")]
+    spawner: Spawner,
+    last_render: instant::Instant,
+    // Rendering is suspended while the window is minimized (zero sized).
+    minimized: bool,
+    #[cfg(target_arch = "wasm32")]
+    offscreen_canvas_setup: OffscreenCanvasSetup,
+}
 
-    let spawner = Spawner::new();
+impl<A: Application> LoopState<A> {
 
-    let mut input = InputCache::init();
+    /// Split the configuration into the loop state and the event loop. name is used in the
+    /// panic messages.
+    fn init(application: A, configuration: WGPUConfiguration, name: &str) -> (Self, EventLoop<()>) {
 
-    // Launch the loop.
-    event_loop.run(move |event, _, control_flow| {
+        let WGPUConfiguration {
+            window,
+            event_loop,
+            instance,
+            size,
+            surface,
+            adapter,
+            device,
+            queue,
+            sc_desc,
+            config,
+            #[cfg(target_arch = "wasm32")]
+            offscreen_canvas_setup
+        } = configuration;
 
-        // Force the ownerships to this closure.
-        let _ = (&window,
-                &instance,
-                &mut size,
-                &surface,
-                &adapter,
-                &device,
-                &mut queue,
-                &mut sc_desc,
-                &mut application,
-                &mut input,
-                &spawner,
-                #[cfg(target_arch = "wasm32")]
-                &offscreen_canvas_setup
-                );
+        let window = window.unwrap_or_else(|| panic!("{} requires a window.", name));
+        let event_loop = event_loop.unwrap_or_else(|| panic!("{} requires an event loop.", name));
+        let surface = surface.unwrap_or_else(|| panic!("{} requires a surface.", name));
 
-        *control_flow = ControlFlow::Poll;
-        //*control_flow = ControlFlow::Wait;
+        let mut frame_stats = FrameStats::new(config.frame_stats_window());
+        frame_stats.set_report_interval(config.frame_stats_report());
 
-        match event {
+        let state = Self {
+            application,
+            window,
+            _instance: instance,
+            _adapter: adapter,
+            size,
+            surface,
+            device,
+            queue,
+            sc_desc,
+            config,
+            input: InputCache::init(),
+            frame_stats,
+            spawner: Spawner::new(),
+            last_render: instant::Instant::now(),
+            minimized: false,
+            #[cfg(target_arch = "wasm32")]
+            offscreen_canvas_setup,
+        };
 
-            // Event::NewEvents(start_cause) => {
-            //     match start_cause {
-            //         Init => {}
-            //         _ => {}
-            //     }
-            // }
+        (state, event_loop)
+    }
 
-            Event::LoopDestroyed => {
-                application.exit(&device, &queue, &input, &spawner);
-            }
+    /// Start the phase timings of this iteration, run Application::input and record the Input
+    /// phase. Iterations that don't render don't count into the next frame.
+    fn input(&mut self) {
+        self.frame_stats.reset_phases();
+        let phase_start = instant::Instant::now();
+        self.application.input(&self.queue, &self.input);
+        self.frame_stats.record_phase(FramePhase::Input, phase_start.elapsed());
+    }
 
-            // TODO: check if pre_update and update are conficting in some circumstances.
-            Event::MainEventsCleared => {
-                application.input(&queue, &input);
-                application.update(&device, &queue, &input, &spawner);
-                input.pre_update();
-                window.request_redraw();
+    /// Request a redraw unless the window is minimized.
+    fn request_redraw(&self) {
+        if !self.minimized {
+            self.window.request_redraw();
+        }
+    }
+
+    /// Handle the events that are the same for every windowed loop. Returns true if the event
+    /// was a window event.
+    fn handle_event(&mut self, event: &Event<()>, control_flow: &mut ControlFlow) -> bool {
+
+        match event {
+
+            Event::LoopDestroyed => {
+                self.application.exit(&self.device, &self.queue, &self.input, &self.spawner);
             }
             Event::RedrawEventsCleared => {
                 #[cfg(not(target_arch = "wasm32"))]
                 {
-                    spawner.run_until_stalled();
+                    // Finish the pending buffer mappings (see ReadbackPool).
+                    self.device.poll(wgpu::Maintain::Poll);
+                    self.spawner.run_until_stalled();
                 }
 
-                let close_application = input.key_state(&Key::Q);
-                if close_application.is_some() {
+                let quit_key_pressed = self.config.quit_key().and_then(|key| self.input.key_state(&key)).is_some();
+                let request = if quit_key_pressed { ExitRequest::QuitKey } else { ExitRequest::None };
+                if self.application.should_exit(&self.input, request) {
                     *control_flow = ControlFlow::Exit;
                 }
             }
             Event::WindowEvent { event, ..} => {
                 // Update input cache.
-                input.update(&event);
-
-                match event { // Add ScaleFactorChanged.
-                    WindowEvent::Resized(new_size) => {
-                        size = new_size;
-                        sc_desc.width = new_size.width.max(1);
-                        sc_desc.height = new_size.height.max(1);
-                        surface.configure(&device, &sc_desc);
-                        application.resize(&device, &sc_desc, size);
+                self.input.update(event);
+
+                let new_size = match event {
+                    WindowEvent::Resized(new_size) => Some(*new_size),
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => Some(**new_inner_size),
+                    _ => None,
+                };
+
+                if let Some(new_size) = new_size {
+                    self.minimized = !configure_surface(&self.surface, &self.device, &mut self.sc_desc, new_size);
+                    if !self.minimized {
+                        self.size = new_size;
+                        self.application.resize(&self.device, &self.sc_desc, self.size);
                     }
-                    WindowEvent::CloseRequested => {
+                }
+
+                if let WindowEvent::CloseRequested = event {
+                    if self.application.should_exit(&self.input, ExitRequest::CloseRequested) {
                         *control_flow = ControlFlow::Exit
                     }
-                    _ => {}
                 }
+                return true;
             }
-            Event::RedrawRequested(_) => {
+            Event::RedrawRequested(_) if !self.minimized => {
+                let phase_start = instant::Instant::now();
+
                 #[cfg(not(target_arch = "wasm32"))]
-                application.render(&device, &mut queue, &surface, &sc_desc, &spawner);
+                self.application.render(&self.device, &mut self.queue, &RenderTarget::Surface(&self.surface), &self.sc_desc, &self.spawner);
 
                 #[cfg(target_arch = "wasm32")]
-                application.render(&device, &mut queue, &surface, &sc_desc, &offscreen_canvas_setup, &spawner);
+                self.application.render(&self.device, &mut self.queue, &RenderTarget::Surface(&self.surface), &self.sc_desc, &self.offscreen_canvas_setup, &self.spawner);
+
+                self.frame_stats.record_phase(FramePhase::Render, phase_start.elapsed());
+                self.frame_stats.end_frame(self.last_render.elapsed());
+                self.last_render = instant::Instant::now();
+                self.application.frame_finished(&self.frame_stats);
             }
             _ => { } // Any other events
+        }
+        false
+    }
+}
+
+/// A "basic" loop. The redraw mode is chosen with AppConfig::with_redraw_mode.
+pub struct BasicLoop { }
+
+impl Loop for BasicLoop {
+
+    fn init() -> Self {
+        BasicLoop {}
+    }
+
+    fn run<A: Application>(&self, application: A, configuration: WGPUConfiguration) {
+
+    let (mut state, event_loop) = LoopState::init(application, configuration, "BasicLoop");
+
+    // Is there a redraw request that hasn't been handled yet (on-demand modes only).
+    let mut redraw_pending = true;
+
+    // Launch the loop.
+    event_loop.run(move |event, _, control_flow| {
+
+        match event {
+
+            // Periodic redraw.
+            Event::NewEvents(StartCause::ResumeTimeReached { .. }) => {
+                redraw_pending = true;
+            }
+
+            // Woken up by RedrawRequester.
+            Event::UserEvent(()) => {
+                redraw_pending = true;
+            }
+
+            // TODO: check if pre_update and update are conficting in some circumstances.
+            Event::MainEventsCleared => {
+                state.input();
+
+                let phase_start = instant::Instant::now();
+                state.application.update(&state.device, &state.queue, &state.input, &state.spawner);
+                state.frame_stats.record_phase(FramePhase::Update, phase_start.elapsed());
+
+                let redraw = match state.config.redraw_mode() {
+                    RedrawMode::Continuous => true,
+                    _ => redraw_pending || state.input.is_active() || state.application.needs_redraw(&state.input),
+                };
+
+                state.input.pre_update();
+
+                if redraw && !state.minimized {
+                    redraw_pending = false;
+                    state.request_redraw();
+                }
+
+                // Keep polling while redrawing so that held keys etc. take effect.
+                if *control_flow != ControlFlow::Exit {
+                    *control_flow = match state.config.redraw_mode() {
+                        _ if state.minimized => ControlFlow::Wait,
+                        RedrawMode::Continuous => ControlFlow::Poll,
+                        _ if redraw => ControlFlow::Poll,
+                        RedrawMode::OnDemand => ControlFlow::Wait,
+                        RedrawMode::Periodic(interval) => ControlFlow::WaitUntil(instant::Instant::now() + interval),
+                    };
+                }
+            }
+            event => {
+                if state.handle_event(&event, control_flow) {
+                    redraw_pending = true;
+                }
+            }
         } // match event
     }); // run
     }
 }
 
-/// Initializes wgpu-rs system. TODO: finish the Result<...>.
-pub async fn setup<P: WGPUFeatures>(title: &str) -> Result<WGPUConfiguration, &'static str> {
+/// A loop that runs Application::fixed_update with a fixed time step. The frame time is
+/// accumulated and consumed in ticks, at most max_substeps ticks per frame. The simulation can
+/// be paused, single-stepped and sped up or slowed down with the keys of FixedStepKeys. The loop
+/// always redraws continuously and ignores the redraw mode of AppConfig.
+pub struct FixedStepLoop {
+    tick_rate: f64,
+    max_substeps: u32,
+    keys: FixedStepKeys,
+}
+
+impl FixedStepLoop {
+
+    /// Set the number of ticks per second.
+    pub fn with_tick_rate(mut self, tick_rate: f64) -> Self {
+        self.tick_rate = tick_rate;
+        self
+    }
+
+    /// Set the maximum number of ticks per frame.
+    pub fn with_max_substeps(mut self, max_substeps: u32) -> Self {
+        self.max_substeps = max_substeps;
+        self
+    }
+
+    /// Set the pause, single-step and time scale keys.
+    pub fn with_keys(mut self, keys: FixedStepKeys) -> Self {
+        self.keys = keys;
+        self
+    }
+}
+
+impl Loop for FixedStepLoop {
+
+    /// 60 ticks per second and at most 8 ticks per frame.
+    fn init() -> Self {
+        FixedStepLoop {
+            tick_rate: 60.0,
+            max_substeps: 8,
+            keys: FixedStepKeys::default(),
+        }
+    }
+
+    fn run<A: Application>(&self, application: A, configuration: WGPUConfiguration) {
+
+    let (mut state, event_loop) = LoopState::init(application, configuration, "FixedStepLoop");
+
+    let mut clock = FixedStepClock::new(self.tick_rate, self.max_substeps);
+    let keys = self.keys;
+    let mut last_frame = instant::Instant::now();
+
+    // Launch the loop.
+    event_loop.run(move |event, _, control_flow| {
+
+        match event {
+
+            Event::MainEventsCleared => {
+                let now = instant::Instant::now();
+                let frame_time = (now - last_frame).as_secs_f64();
+                last_frame = now;
+
+                state.input();
+                clock.apply_keys(&keys, &state.input);
+
+                let phase_start = instant::Instant::now();
+                for _ in 0..clock.advance(frame_time) {
+                    state.application.fixed_update(&state.device, &state.queue, &state.input, clock.time_step(), &state.spawner);
+                }
+                state.frame_stats.record_phase(FramePhase::Update, phase_start.elapsed());
+                state.application.set_interpolation_alpha(clock.alpha());
+
+                state.input.pre_update();
+                state.request_redraw();
+
+                if *control_flow != ControlFlow::Exit {
+                    *control_flow = if state.minimized { ControlFlow::Wait } else { ControlFlow::Poll };
+                }
+            }
+            event => {
+                state.handle_event(&event, control_flow);
+            }
+        } // match event
+    }); // run
+    }
+}
+
+/// A loop that renders a fixed number of frames into an offscreen color texture and writes
+/// the frames as png files. There is no window, the clock advances time_step nanoseconds per
+/// frame. Native only.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct OffscreenLoop {
+    frame_count: u32,
+    time_step: u128,
+    saved_frames: Option<Vec<u32>>,
+    output_dir: Option<PathBuf>,
+    file_prefix: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl OffscreenLoop {
+
+    /// Set the number of rendered frames.
+    pub fn with_frame_count(mut self, frame_count: u32) -> Self {
+        self.frame_count = frame_count;
+        self
+    }
+
+    /// Set the time step of the deterministic clock in nanoseconds.
+    pub fn with_time_step(mut self, time_step: u128) -> Self {
+        self.time_step = time_step;
+        self
+    }
+
+    /// Write only the given frames (indices starting from 0). By default every frame is written.
+    pub fn with_saved_frames(mut self, frames: &[u32]) -> Self {
+        self.saved_frames = Some(frames.to_vec());
+        self
+    }
+
+    /// Set the directory for the png files. None disables writing.
+    pub fn with_output_dir<P: AsRef<Path>>(mut self, output_dir: Option<P>) -> Self {
+        self.output_dir = output_dir.map(|p| p.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the prefix of the png file names. The files are named <prefix><frame>.png.
+    pub fn with_file_prefix(mut self, file_prefix: &str) -> Self {
+        self.file_prefix = file_prefix.to_owned();
+        self
+    }
+
+    /// Get the number of rendered frames.
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    /// Render the frames and return the rgba8 data of the last frame. Doesn't call
+    /// Application::exit.
+    pub fn render_frames<A: Application>(&self, application: &mut A, configuration: &mut WGPUConfiguration) -> Vec<u8> {
+
+        let spawner = Spawner::new();
+        let mut input = InputCache::init();
+
+        let color_target = Texture::create_render_target(&configuration.device, &configuration.sc_desc, Some("offscreen color target"));
+
+        if let Some(dir) = &self.output_dir {
+            if let Err(e) = std::fs::create_dir_all(dir) {
+                log::error!("Couldn't create the output directory {:?}: {}", dir, e);
+            }
+        }
+
+        let mut pixels: Vec<u8> = Vec::new();
+
+        for frame in 0..self.frame_count {
+
+            application.input(&configuration.queue, &input);
+            application.update(&configuration.device, &configuration.queue, &input, &spawner);
+            input.pre_update_with_delta(self.time_step);
+
+            application.render(&configuration.device,
+                               &mut configuration.queue,
+                               &RenderTarget::Offscreen(&color_target),
+                               &configuration.sc_desc,
+                               &spawner);
+            configuration.device.poll(wgpu::Maintain::Poll);
+            spawner.run_until_stalled();
+
+            let last_frame = frame + 1 == self.frame_count;
+            let save = self.output_dir.is_some() && match &self.saved_frames {
+                Some(frames) => frames.contains(&frame),
+                None => true,
+            };
+
+            if !(save || last_frame) { continue; }
+
+            pixels = pollster::block_on(color_target.to_vec::<u8>(&configuration.device, &configuration.queue));
+            match configuration.sc_desc.format {
+                wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => swap_red_blue(&mut pixels),
+                _ => {}
+            }
+
+            if save {
+                let path = self.output_dir.as_ref().unwrap().join(format!("{}{:05}.png", self.file_prefix, frame));
+                match write_png_rgba8(&path, color_target.width(), color_target.height(), &pixels) {
+                    Ok(_) => log::info!("Wrote {:?}.", path),
+                    Err(e) => log::error!("Couldn't write {:?}: {}", path, e),
+                }
+            }
+        }
+
+        pixels
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Loop for OffscreenLoop {
+
+    /// Renders one frame to the "frames" directory at 60 frames per second.
+    fn init() -> Self {
+        OffscreenLoop {
+            frame_count: 1,
+            time_step: 1_000_000_000 / 60,
+            saved_frames: None,
+            output_dir: Some(PathBuf::from("frames")),
+            file_prefix: "frame_".to_owned(),
+        }
+    }
+
+    fn run<A: Application>(&self, mut application: A, mut configuration: WGPUConfiguration) {
+
+        let _ = self.render_frames(&mut application, &mut configuration);
+
+        let spawner = Spawner::new();
+        let input = InputCache::init();
+        application.exit(&configuration.device, &configuration.queue, &input, &spawner);
+    }
+}
+
+/// Reconfigures the surface for the new window size. Returns false if the window is minimized
+/// (zero sized), in which case the surface isn't touched.
+fn configure_surface(surface: &wgpu::Surface,
+                     device: &wgpu::Device,
+                     sc_desc: &mut wgpu::SurfaceConfiguration,
+                     new_size: winit::dpi::PhysicalSize<u32>) -> bool {
+    if new_size.width == 0 || new_size.height == 0 {
+        return false;
+    }
+    sc_desc.width = new_size.width;
+    sc_desc.height = new_size.height;
+    surface.configure(device, sc_desc);
+    true
+}
+
+/// Initializes wgpu-rs system. The window and the surface are created using config.
+pub async fn setup<P: WGPUFeatures>(config: AppConfig) -> Result<WGPUConfiguration, SetupError> {
+    setup_with_feature_sets(config, &[FeatureSet::of::<P>()]).await
+}
+
+/// Initializes wgpu-rs system. If the adapter doesn't support the requirements of P, the
+/// requirements of Q are tried instead.
+pub async fn setup_with_fallback<P: WGPUFeatures, Q: WGPUFeatures>(config: AppConfig) -> Result<WGPUConfiguration, SetupError> {
+    setup_with_feature_sets(config, &[FeatureSet::of::<P>(), FeatureSet::of::<Q>()]).await
+}
 
-    let title = title.to_owned();
+/// Initializes wgpu-rs system using the first feature set that the adapter supports.
+async fn setup_with_feature_sets(config: AppConfig, feature_sets: &[FeatureSet]) -> Result<WGPUConfiguration, SetupError> {
 
     let event_loop = EventLoop::new();
-    let mut builder = winit::window::WindowBuilder::new();
-    builder = builder.with_title(title);
+    #[allow(unused_mut)]
+    let mut builder = config.window_builder();
     #[cfg(windows_OFF)] // TODO
     {
         use winit::platform::windows::WindowBuilderExtWindows;
         builder = builder.with_no_redirection_bitmap(true);
         log::info!("windows_OFF :: True");
     }
-    let window = builder.build(&event_loop).unwrap();
+    let window = builder.build(&event_loop).map_err(SetupError::Window)?;
 
     #[cfg(target_arch = "wasm32")]
     {
         use winit::platform::web::WindowExtWebSys;
-        console_log::init_with_level(log::Level::Trace).expect("could not initialize logger");
+        // The only error is an already installed logger (e.g. a second setup), keep that one.
+        let _ = console_log::init_with_level(log::Level::Trace);
         std::panic::set_hook(Box::new(console_error_panic_hook::hook));
         // On wasm, append the canvas to the document body
         web_sys::window()
@@ -243,48 +755,37 @@ pub async fn setup<P: WGPUFeatures>(title: &str) -> Result<WGPUConfiguration, &'
                 body.append_child(&web_sys::Element::from(window.canvas()))
                     .ok()
             })
-            .expect("couldn't append canvas to document body");
+            .ok_or(SetupError::Canvas("couldn't append canvas to document body"))?;
     }
 
     #[cfg(target_arch = "wasm32")]
-    let offscreen_canvas = OffscreenCanvas::new(1024, 768).expect("couldn't create OffscreenCanvas");
+    let offscreen_canvas = config.size().map_or(OffscreenCanvas::new(1024, 768), |size| OffscreenCanvas::new(size.width, size.height)).map_err(|_| SetupError::Canvas("couldn't create OffscreenCanvas"))?;
 
     #[cfg(target_arch = "wasm32")]
     let bitmap_renderer = window
         .canvas()
         .get_context("bitmaprenderer")
-        .expect("couldn't create ImageBitmapRenderingContext (Result)")
-        .expect("couldn't create ImageBitmapRenderingContext (Option)")
+        .map_err(|_| SetupError::Canvas("couldn't create ImageBitmapRenderingContext (Result)"))?
+        .ok_or(SetupError::Canvas("couldn't create ImageBitmapRenderingContext (Option)"))?
         .dyn_into::<ImageBitmapRenderingContext>()
-        .expect("couldn't convert into ImageBitmapRenderingContext");
+        .map_err(|_| SetupError::Canvas("couldn't convert into ImageBitmapRenderingContext"))?;
 
     #[cfg(target_arch = "wasm32")]
     let offscreen_canvas_setup = OffscreenCanvasSetup { offscreen_canvas, bitmap_renderer, };
 
-    let backends = wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::all());
-    let dx12_shader_compiler = wgpu::util::dx12_shader_compiler_from_env().unwrap_or_default();
-    let gles_minor_version = wgpu::util::gles_minor_version_from_env().unwrap_or_default();
-    
-    let power_preference = if let Ok(power_preference) = std::env::var("WGPU_POWER_PREF") {
-        match power_preference.to_lowercase().as_str() {
-            "low" => wgpu::PowerPreference::LowPower,
-            "high" => wgpu::PowerPreference::HighPerformance,
-            other => panic!("Unknown power preference: {}", other),
-        }
-    } else {
-        wgpu::PowerPreference::HighPerformance
-    };
-    log::info!("power_preference = {:?}", power_preference);
-    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor { backends, dx12_shader_compiler, gles_minor_version });
+    // Only validated and logged, the adapter is chosen by wgpu::util.
+    let _power_preference = power_preference_from_env()?;
+
+    let instance = create_instance();
     let (size, surface) = unsafe {
 
         let size = window.inner_size();
 
         #[cfg(not(target_arch = "wasm32"))]
-        let surface = instance.create_surface(&window).unwrap();
+        let surface = instance.create_surface(&window).map_err(SetupError::Surface)?;
 
         #[cfg(target_arch = "wasm32")]
-        let surface = instance .create_surface_from_offscreen_canvas(&offscreen_canvas_setup.offscreen_canvas).unwrap();
+        let surface = instance .create_surface_from_offscreen_canvas(&offscreen_canvas_setup.offscreen_canvas).map_err(SetupError::Surface)?;
 
         (size, surface)
     };
@@ -292,7 +793,7 @@ pub async fn setup<P: WGPUFeatures>(title: &str) -> Result<WGPUConfiguration, &'
     //let adapter = wgpu::util::initialize_adapter_from_env_or_default(&instance, backends, Some(&surface))
     let adapter = wgpu::util::initialize_adapter_from_env_or_default(&instance, Some(&surface))
         .await
-        .expect("No suitable GPU adapters found on the system!");
+        .ok_or(SetupError::NoAdapter)?;
 
     #[cfg(not(target_arch = "wasm32"))]
     {
@@ -300,120 +801,325 @@ pub async fn setup<P: WGPUFeatures>(title: &str) -> Result<WGPUConfiguration, &'
         log::info!("Using {} ({:?})", adapter_info.name, adapter_info.backend);
     }
 
-    let optional_features = P::optional_features();
-    let required_features = P::required_features();
-    let adapter_features = adapter.features();
+    let (device, queue) = request_first_device(&adapter, feature_sets).await?;
+      
+    let sc_desc = config.surface_configuration(&surface, &adapter, size)?;
+    log::info!("Surface format {:?}, present mode {:?}, alpha mode {:?}.", sc_desc.format, sc_desc.present_mode, sc_desc.alpha_mode);
 
-    assert!(
-        adapter_features.contains(required_features),
-        "Adapter does not support required features for this example: {:?}",
-        required_features - adapter_features
-    );
+    surface.configure(&device, &sc_desc);
 
-    let required_downlevel_capabilities = P::required_downlevel_capabilities();
-    let downlevel_capabilities = adapter.get_downlevel_capabilities();
-    assert!(
-        downlevel_capabilities.shader_model >= required_downlevel_capabilities.shader_model,
-        "Adapter does not support the minimum shader model required to run this example: {:?}",
-        required_downlevel_capabilities.shader_model
-        );
-    assert!(
-        downlevel_capabilities
-        .flags
-        .contains(required_downlevel_capabilities.flags),
-        "Adapter does not support the downlevel capabilities required to run this example: {:?}",
-        required_downlevel_capabilities.flags - downlevel_capabilities.flags
-        );
-
-    let needed_limits = P::required_limits().using_resolution(adapter.limits());
+    Ok(WGPUConfiguration {
+            window: Some(window),
+            event_loop: Some(event_loop),
+            instance,
+            size,
+            surface: Some(surface),
+            adapter,
+            device,
+            queue,
+            sc_desc,
+            config,
+            #[cfg(target_arch = "wasm32")]
+            offscreen_canvas_setup,
+    })
+}
+
+/// A struct that holds the wgpu-rs resources for compute-only work. There is no window, event loop
+/// or surface.
+pub struct HeadlessConfiguration {
+    pub instance: wgpu::Instance,
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+}
+
+/// Initializes wgpu-rs without a window. If force_fallback_adapter is true, a software/fallback
+/// adapter is requested instead of a hardware one.
+pub async fn setup_headless<P: WGPUFeatures>(force_fallback_adapter: bool) -> Result<HeadlessConfiguration, SetupError> {
+    setup_headless_with_feature_sets(force_fallback_adapter, &[FeatureSet::of::<P>()]).await
+}
+
+/// Initializes wgpu-rs without a window. If the adapter doesn't support the requirements of P,
+/// the requirements of Q are tried instead.
+pub async fn setup_headless_with_fallback<P: WGPUFeatures, Q: WGPUFeatures>(force_fallback_adapter: bool) -> Result<HeadlessConfiguration, SetupError> {
+    setup_headless_with_feature_sets(force_fallback_adapter, &[FeatureSet::of::<P>(), FeatureSet::of::<Q>()]).await
+}
+
+/// Initializes wgpu-rs without a window using the first feature set that the adapter supports.
+async fn setup_headless_with_feature_sets(force_fallback_adapter: bool, feature_sets: &[FeatureSet]) -> Result<HeadlessConfiguration, SetupError> {
+
+    let instance = create_instance();
+
+    let adapter = if force_fallback_adapter {
+        instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: power_preference_from_env()?,
+            force_fallback_adapter: true,
+            compatible_surface: None,
+        }).await
+    } else {
+        wgpu::util::initialize_adapter_from_env_or_default(&instance, None).await
+    };
+
+    let adapter = adapter.ok_or(SetupError::NoAdapter)?;
+
+    let adapter_info = adapter.get_info();
+    log::info!("Using {} ({:?}) headless", adapter_info.name, adapter_info.backend);
+
+    let (device, queue) = request_first_device(&adapter, feature_sets).await?;
+
+    Ok(HeadlessConfiguration {
+        instance,
+        adapter,
+        device,
+        queue,
+    })
+}
+
+/// Initializes wgpu-rs without a window and blocks until the device is ready. Native version.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn setup_headless_blocking<P: WGPUFeatures>(force_fallback_adapter: bool) -> Result<HeadlessConfiguration, SetupError> {
+    pollster::block_on(setup_headless::<P>(force_fallback_adapter))
+}
+
+/// Initializes wgpu-rs for offscreen rendering. The resulting configuration has no window,
+/// event loop or surface. sc_desc describes the offscreen color target (Rgba8UnormSrgb).
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn setup_offscreen<P: WGPUFeatures>(width: u32, height: u32, force_fallback_adapter: bool) -> Result<WGPUConfiguration, SetupError> {
+
+    let HeadlessConfiguration { instance, adapter, device, queue } = setup_headless::<P>(force_fallback_adapter).await?;
 
-    let trace_dir = std::env::var("WGPU_TRACE");
-    let (device, queue) = adapter
-        .request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                features: (optional_features & adapter_features) | required_features,
-                limits: needed_limits,
-            },
-            trace_dir.ok().as_ref().map(std::path::Path::new),
-        )
-        .await
-        .expect("Unable to find a suitable GPU adapter!");
-      
     let sc_desc = wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-        format: surface.get_capabilities(&adapter).formats[0],
-        width: size.width,
-        height: size.height,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        width,
+        height,
         present_mode: wgpu::PresentMode::Fifo,
-        alpha_mode: surface.get_capabilities(&adapter).alpha_modes[0],
-        view_formats: vec![wgpu::TextureFormat::Bgra8Unorm],
+        alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+        view_formats: vec![],
     };
 
-    surface.configure(&device, &sc_desc);
-
     Ok(WGPUConfiguration {
-            window,
-            event_loop,
+            window: None,
+            event_loop: None,
             instance,
-            size,
-            surface,
+            size: winit::dpi::PhysicalSize::new(width, height),
+            surface: None,
             adapter,
             device,
             queue,
             sc_desc,
-            #[cfg(target_arch = "wasm32")]
-            offscreen_canvas_setup,
+            config: AppConfig::default().with_size(width, height),
     })
 }
 
+/// Creates the wgpu::Instance. The backends, dx12 compiler and gles version can be chosen
+/// with the wgpu environment variables.
+fn create_instance() -> wgpu::Instance {
+    let backends = wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::all());
+    let dx12_shader_compiler = wgpu::util::dx12_shader_compiler_from_env().unwrap_or_default();
+    let gles_minor_version = wgpu::util::gles_minor_version_from_env().unwrap_or_default();
+    wgpu::Instance::new(wgpu::InstanceDescriptor { backends, dx12_shader_compiler, gles_minor_version })
+}
+
+/// Reads the power preference from WGPU_POWER_PREF environment variable ("low" or "high").
+fn power_preference_from_env() -> Result<wgpu::PowerPreference, SetupError> {
+    let power_preference = if let Ok(power_preference) = std::env::var("WGPU_POWER_PREF") {
+        match power_preference.to_lowercase().as_str() {
+            "low" => wgpu::PowerPreference::LowPower,
+            "high" => wgpu::PowerPreference::HighPerformance,
+            other => return Err(SetupError::UnknownPowerPreference(other.to_owned())),
+        }
+    } else {
+        wgpu::PowerPreference::HighPerformance
+    };
+    log::info!("power_preference = {:?}", power_preference);
+    Ok(power_preference)
+}
+
+/// Checks that the adapter supports the required features and downlevel capabilities of the
+/// feature set.
+pub fn check_adapter(adapter: &wgpu::Adapter, feature_set: &FeatureSet) -> Result<(), SetupError> {
+
+    let adapter_features = adapter.features();
+    if !adapter_features.contains(feature_set.required_features) {
+        return Err(SetupError::MissingFeatures(feature_set.required_features - adapter_features));
+    }
+
+    let required_downlevel_capabilities = &feature_set.required_downlevel_capabilities;
+    let downlevel_capabilities = adapter.get_downlevel_capabilities();
+    if downlevel_capabilities.shader_model < required_downlevel_capabilities.shader_model {
+        return Err(SetupError::ShaderModel {
+            required: required_downlevel_capabilities.shader_model,
+            supported: downlevel_capabilities.shader_model,
+        });
+    }
+    if !downlevel_capabilities.flags.contains(required_downlevel_capabilities.flags) {
+        return Err(SetupError::MissingDownlevelFlags(required_downlevel_capabilities.flags - downlevel_capabilities.flags));
+    }
+
+    Ok(())
+}
+
+/// Checks the feature set against the adapter and requests the device and queue.
+async fn request_device(adapter: &wgpu::Adapter, feature_set: &FeatureSet) -> Result<(wgpu::Device, wgpu::Queue), SetupError> {
+
+    check_adapter(adapter, feature_set)?;
+
+    let needed_limits = feature_set.required_limits.clone().using_resolution(adapter.limits());
+
+    let trace_dir = std::env::var("WGPU_TRACE");
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: (feature_set.optional_features & adapter.features()) | feature_set.required_features,
+                limits: needed_limits,
+            },
+            trace_dir.ok().as_ref().map(std::path::Path::new),
+        )
+        .await
+        .map_err(SetupError::RequestDevice)
+}
+
+/// Requests the device using the first feature set that succeeds. The error of the last
+/// feature set is returned if none of them succeeds.
+async fn request_first_device(adapter: &wgpu::Adapter, feature_sets: &[FeatureSet]) -> Result<(wgpu::Device, wgpu::Queue), SetupError> {
+
+    assert!(!feature_sets.is_empty(), "request_first_device: no feature sets.");
+
+    let mut error = None;
+    for (i, feature_set) in feature_sets.iter().enumerate() {
+        match request_device(adapter, feature_set).await {
+            Ok(device_and_queue) => return Ok(device_and_queue),
+            Err(e) => {
+                if i + 1 < feature_sets.len() {
+                    log::warn!("{}. Trying the fallback feature set.", e);
+                }
+                error = Some(e);
+            }
+        }
+    }
+    Err(error.unwrap())
+}
+
 /// Initializes wgpu-rs basic components, application and starts the loop. Native version.
 #[cfg(not(target_arch = "wasm32"))]
-pub fn run_loop<A: Application, L: Loop, F: WGPUFeatures>() {
+pub fn run_loop<A: Application, L: Loop, F: WGPUFeatures>(config: AppConfig) {
+    log::info!("Setting up wgpu-rs.");
+    launch::<A, L>(L::init(), pollster::block_on(setup::<F>(config)));
+}
+
+/// The same as run_loop, but uses the given loop instead of L::init(). Native version.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_loop_with<A: Application, L: Loop, F: WGPUFeatures>(lo: L, config: AppConfig) {
+    log::info!("Setting up wgpu-rs.");
+    launch::<A, L>(lo, pollster::block_on(setup::<F>(config)));
+}
+
+/// The same as run_loop, but the requirements of G are tried if the adapter doesn't support F.
+/// Native version.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_loop_with_fallback<A: Application, L: Loop, F: WGPUFeatures, G: WGPUFeatures>(config: AppConfig) {
     log::info!("Setting up wgpu-rs.");
-    let configuration = pollster::block_on(setup::<F>("jihuu")).expect("Failed to create WGPUConfiguration.");
+    launch::<A, L>(L::init(), pollster::block_on(setup_with_fallback::<F, G>(config)));
+}
+
+/// Initializes the application and starts the loop. Setup errors are logged and the process
+/// exits with status 1.
+#[cfg(not(target_arch = "wasm32"))]
+fn launch<A: Application, L: Loop>(lo: L, configuration: Result<WGPUConfiguration, SetupError>) {
+    let configuration = match configuration {
+        Ok(configuration) => configuration,
+        Err(e) => {
+            log::error!("Failed to create WGPUConfiguration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    log::info!("Configurating application.");
+    let app = A::init(&configuration);
+    log::info!("Launching the application.");
+    lo.run(app, configuration); 
+}
+
+/// Initializes wgpu-rs without a window, application and runs the offscreen loop. Exits with
+/// status 1 if the setup fails.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_offscreen_loop<A: Application, F: WGPUFeatures>(lo: OffscreenLoop, width: u32, height: u32) {
+    log::info!("Setting up wgpu-rs (offscreen).");
+    let configuration = match pollster::block_on(setup_offscreen::<F>(width, height, false)) {
+        Ok(configuration) => configuration,
+        Err(e) => {
+            log::error!("Failed to create WGPUConfiguration: {}", e);
+            std::process::exit(1);
+        }
+    };
     log::info!("Configurating application.");
     let app = A::init(&configuration);
-    log::info!("Initializing loop.");
-    let lo = L::init();
     log::info!("Launching the application.");
     lo.run(app, configuration); 
 }
 
 /// Initializes wgpu-rs basic components, application and starts the loop. wasm version.
 #[cfg(target_arch = "wasm32")]
-pub fn run_loop<A: Application, L: Loop, F: WGPUFeatures>() {
-    use wasm_bindgen::{prelude::*, JsCast};
+pub fn run_loop<A: Application, L: Loop, F: WGPUFeatures>(config: AppConfig) {
+    wasm_bindgen_futures::spawn_local(async move {
+        log::info!("Setting up wgpu-rs.");
+        launch::<A, L>(L::init(), setup::<F>(config).await);
+    });
+}
 
+/// The same as run_loop, but uses the given loop instead of L::init(). wasm version.
+#[cfg(target_arch = "wasm32")]
+pub fn run_loop_with<A: Application, L: Loop, F: WGPUFeatures>(lo: L, config: AppConfig) {
     wasm_bindgen_futures::spawn_local(async move {
+        log::info!("Setting up wgpu-rs.");
+        launch::<A, L>(lo, setup::<F>(config).await);
+    });
+}
 
+/// The same as run_loop, but the requirements of G are tried if the adapter doesn't support F.
+/// wasm version.
+#[cfg(target_arch = "wasm32")]
+pub fn run_loop_with_fallback<A: Application, L: Loop, F: WGPUFeatures, G: WGPUFeatures>(config: AppConfig) {
+    wasm_bindgen_futures::spawn_local(async move {
         log::info!("Setting up wgpu-rs.");
-        let configuration = setup::<F>("jihuu").await.unwrap();
+        launch::<A, L>(L::init(), setup_with_fallback::<F, G>(config).await);
+    });
+}
 
-        log::info!("Configurating application.");
-        let app = A::init(&configuration); 
+/// Initializes the application and starts the loop. Setup errors are written to the console.
+#[cfg(target_arch = "wasm32")]
+fn launch<A: Application, L: Loop>(lo: L, configuration: Result<WGPUConfiguration, SetupError>) {
+    use wasm_bindgen::{prelude::*, JsCast};
 
-        log::info!("Creating the application.");
-        let lo = L::init();
+    let configuration = match configuration {
+        Ok(configuration) => configuration,
+        Err(e) => {
+            web_sys::console::error_1(&format!("Failed to create WGPUConfiguration: {}", e).into());
+            return;
+        }
+    };
 
-        let start_closure = Closure::once_into_js(move || lo.run(app, configuration));
+    log::info!("Configurating application.");
+    let app = A::init(&configuration); 
 
-        if let Err(error) = call_catch(&start_closure) {
-            let is_control_flow_exception = error.dyn_ref::<js_sys::Error>().map_or(false, |e| {
-                    e.message().includes("Using exceptions for control flow", 0)
-                    });
+    let start_closure = Closure::once_into_js(move || lo.run(app, configuration));
 
-            if !is_control_flow_exception {
-                web_sys::console::error_1(&error);
-            }
-        }
+    if let Err(error) = call_catch(&start_closure) {
+        let is_control_flow_exception = error.dyn_ref::<js_sys::Error>().map_or(false, |e| {
+                e.message().includes("Using exceptions for control flow", 0)
+                });
 
-        #[wasm_bindgen]
-        extern "C" {
-            #[wasm_bindgen(catch, js_namespace = Function, js_name = "prototype.call.call")]
-            fn call_catch(this: &JsValue) -> Result<(), JsValue>;
+        if !is_control_flow_exception {
+            web_sys::console::error_1(&error);
         }
-    });
+    }
+
+    #[wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(catch, js_namespace = Function, js_name = "prototype.call.call")]
+        fn call_catch(this: &JsValue) -> Result<(), JsValue>;
+    }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
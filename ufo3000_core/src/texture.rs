@@ -1,4 +1,9 @@
-use crate::misc::Convert2Vec;
+use crate::misc::vec_from_bytes;
+use crate::readback::map_read;
+use crate::app_config::ColorSpace;
+use crate::image::{ImageError, ImageOptions, decode_png, premultiply_alpha, encode_texels};
+use crate::float_image::{FloatImage, RawVolume, decode_hdr, decode_npy};
+use crate::mipmap::{MipFilter, MipmapGenerator, mip_level_count};
 // use std::num::NonZeroU32;
 use bytemuck::Pod;
 
@@ -16,6 +21,21 @@ impl Texture {
 
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
+    /// Get the width of the texture.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of the texture.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get the depth (or the number of array layers) of the texture.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
     /// Create a depth texture.
     pub fn create_depth_texture(device: &wgpu::Device, sc_desc: &wgpu::SurfaceConfiguration, label: Option<&str>) -> Self {
 
@@ -57,20 +77,106 @@ impl Texture {
         Self { texture, view, sampler, width, height, depth }
     }
 
-    /// Creates a texture from a sequency of bytes (expects bytes to be in png format 'rgb'). Alpha value is set to 255.
-    /// Returns a rgba texture.
-    /// TODO: give alpha value as function parameter.
-    /// TODO: check if aplha value already exists.
-    /// TODO: allow a texture to been created from non png data.
-    /// TODO: sample_count is not used. Do we need it?
-    pub fn create_from_bytes(queue: &wgpu::Queue, device: &wgpu::Device, sc_desc: &wgpu::SurfaceConfiguration, sample_count : u32, bytes: &[u8], label: Option<&str>) -> Self {
+    /// Create a color texture that can be used as a render target instead of the surface
+    /// texture. The size and format are taken from sc_desc. The texture can be copied back with
+    /// Texture::to_vec.
+    pub fn create_render_target(device: &wgpu::Device, sc_desc: &wgpu::SurfaceConfiguration, label: Option<&str>) -> Self {
+
+        let width = sc_desc.width; 
+        let height = sc_desc.height; 
+        let depth = 1; 
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: depth,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: sc_desc.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT |
+                   wgpu::TextureUsages::TEXTURE_BINDING |
+                   wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self { texture, view, sampler, width, height, depth }
+    }
+
+    /// Creates a texture with a box filtered mip chain from png bytes with the surface format.
+    /// The image is treated as sRGB if the surface format is an Srgb format. Panics if the
+    /// image can't be loaded. sample_count must be 1, since a multisampled texture can't be
+    /// written or have mipmaps. Use from_image_bytes for the other options.
+    pub fn create_from_bytes(queue: &wgpu::Queue, device: &wgpu::Device, sc_desc: &wgpu::SurfaceConfiguration, sample_count: u32, bytes: &[u8], label: Option<&str>) -> Self {
+        assert!(sample_count == 1, "create_from_bytes: sample_count must be 1, got {}.", sample_count);
+        let options = ImageOptions {
+            format: Some(sc_desc.format),
+            color_space: if sc_desc.format.is_srgb() { ColorSpace::Srgb } else { ColorSpace::Linear },
+            mipmaps: Some(MipFilter::Box),
+            ..ImageOptions::default()
+        };
+        Self::from_image_bytes(device, queue, bytes, &options, label).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Creates a 2d texture from png bytes. See ImageOptions. The mip chain is generated with
+    /// a new MipmapGenerator, use from_image_bytes_with to share one between textures.
+    pub fn from_image_bytes(device: &wgpu::Device,
+                            queue: &wgpu::Queue,
+                            bytes: &[u8],
+                            options: &ImageOptions,
+                            label: Option<&str>) -> Result<Self, ImageError> {
+        Self::decode_image_bytes(device, queue, None, bytes, options, label)
+    }
+
+    /// Like from_image_bytes, but the mip chain is generated with the given generator.
+    pub fn from_image_bytes_with(device: &wgpu::Device,
+                                 queue: &wgpu::Queue,
+                                 generator: &mut MipmapGenerator,
+                                 bytes: &[u8],
+                                 options: &ImageOptions,
+                                 label: Option<&str>) -> Result<Self, ImageError> {
+        Self::decode_image_bytes(device, queue, Some(generator), bytes, options, label)
+    }
+
+    fn decode_image_bytes(device: &wgpu::Device,
+                          queue: &wgpu::Queue,
+                          generator: Option<&mut MipmapGenerator>,
+                          bytes: &[u8],
+                          options: &ImageOptions,
+                          label: Option<&str>) -> Result<Self, ImageError> {
 
         #[cfg(feature = "texture_debug")]
         {
-            log::info!("Creating texture using 'ufo3000_core::Texture::create_from_bytes'.");
+            log::info!("Creating texture using 'ufo3000_core::Texture::from_image_bytes'.");
             log::info!("Label: {:?}.", match label { None => "None", Some(s) => s });
         }
 
+        let mut image = decode_png(bytes, options.color_space)?;
+        if options.premultiply_alpha {
+            premultiply_alpha(&mut image.texels);
+        }
+        let format = options.texture_format(&image);
+        let data = encode_texels(&image.texels, format)?;
+
+        #[cfg(feature = "texture_debug")]
+        {
+            log::info!("Source image width {:?}, height {:?}, bit depth {:?}, format {:?}", image.width, image.height, image.bit_depth, format);
+        }
+
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::MirrorRepeat,
             address_mode_v: wgpu::AddressMode::MirrorRepeat,
@@ -80,67 +186,115 @@ impl Texture {
             mipmap_filter: wgpu::FilterMode::Linear,
             lod_min_clamp: -100.0,
             lod_max_clamp: 100.0,
-            compare: None, // Some(wgpu::CompareFunction::Always),
+            compare: None,
             ..Default::default()
         });
 
-        let png = std::io::Cursor::new(bytes);
-        let decoder = png::Decoder::new(png);
-        let (info, mut reader) = decoder.read_info().expect("Can't read info!");
-        let width = info.width;
-        let height = info.height;
-        let bits_per_pixel = info.color_type.samples() as u32;
+        let texture_extent = wgpu::Extent3d {
+            width: image.width,
+            height: image.height,
+            depth_or_array_layers: 1,
+        };
 
-        #[cfg(feature = "texture_debug")]
-        {
-            log::info!("Source image width {:?}, height {:?}, bits_per_pixes {:?}", width, height, bits_per_pixel);
-        }
+        let (mip_levels, mip_usage) = match options.mipmaps {
+            Some(_) => (mip_level_count(image.width, image.height), wgpu::TextureUsages::RENDER_ATTACHMENT),
+            None => (1, wgpu::TextureUsages::empty()),
+        };
 
-        if !(bits_per_pixel == 3 || bits_per_pixel == 4) {
-            panic!("Bits per pixel must be 3 or 4. Bits per pixel == {}", bits_per_pixel); 
-        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size: texture_extent,
+            mip_level_count: mip_levels,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: options.usage | wgpu::TextureUsages::COPY_DST | mip_usage,
+            view_formats: &[],
+        });
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(data.len() as u32 / image.height),
+                rows_per_image: Some(image.height),
+            },
+            texture_extent,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let result = Self {
+            texture,
+            view,
+            sampler,
+            width: image.width,
+            height: image.height,
+            depth: 1,
+        };
 
-        let mut buffer: Vec<u8> = vec![0; (info.width * bits_per_pixel * info.height) as usize ];
-        reader.next_frame(&mut buffer).unwrap();
-
-        // TODO: check the size of the image.
-        let mut temp: Vec<u8> = Vec::new();
-
-        // The png has only rgb components. Add the alpha component to each texel. 
-        if bits_per_pixel == 3 {
-            for i in 0..buffer.len()/3 {
-                let offset = i*3;
-                let red: u8 = buffer[offset];
-                let green: u8 = buffer[offset+1];
-                let blue: u8 = buffer[offset+2];
-                temp.push(blue); // blue
-                temp.push(green); // green
-                temp.push(red); // red
-                temp.push(255); // alpha
+        if let Some(filter) = options.mipmaps {
+            match generator {
+                Some(generator) => result.generate_mipmaps_with(generator, device, queue, filter),
+                None => result.generate_mipmaps(device, queue, filter),
             }
         }
 
+        Ok(result)
+    }
+
+    /// Fill mip levels 1.. from level 0. See MipmapGenerator. This creates the shader and the
+    /// pipeline every time, use generate_mipmaps_with for more than a few textures.
+    pub fn generate_mipmaps(&self, device: &wgpu::Device, queue: &wgpu::Queue, filter: MipFilter) {
+        self.generate_mipmaps_with(&mut MipmapGenerator::init(device), device, queue, filter);
+    }
+
+    /// Fill mip levels 1.. from level 0 with a generator that keeps its pipelines between calls.
+    pub fn generate_mipmaps_with(&self, generator: &mut MipmapGenerator, device: &wgpu::Device, queue: &wgpu::Queue, filter: MipFilter) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Mipmap encoder") });
+        generator.generate(device, &mut encoder, &self.texture, filter);
+        queue.submit(Some(encoder.finish()));
+    }
+
+    /// Creates a float texture from a FloatImage. 3d textures are created if the depth is
+    /// more than 1. The format must be one of the float formats of encode_texels (e.g.
+    /// Rgba16Float, Rgba32Float or R32Float).
+    pub fn from_float_image(device: &wgpu::Device,
+                            queue: &wgpu::Queue,
+                            image: &FloatImage,
+                            format: wgpu::TextureFormat,
+                            label: Option<&str>) -> Result<Self, ImageError> {
+
+        let data = encode_texels(&image.rgba_texels(), format)?;
+
+        let (dimension, view_dimension) = match image.depth {
+            1 => (wgpu::TextureDimension::D2, wgpu::TextureViewDimension::D2),
+            _ => (wgpu::TextureDimension::D3, wgpu::TextureViewDimension::D3),
+        };
+
         let texture_extent = wgpu::Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
+            width: image.width,
+            height: image.height,
+            depth_or_array_layers: image.depth,
         };
 
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
             size: texture_extent,
             mip_level_count: 1,
-            sample_count,
-            dimension: wgpu::TextureDimension::D2,
-            format: sc_desc.format, // wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            sample_count: 1,
+            dimension,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[],
         });
 
-        #[cfg(feature = "texture_debug")]
-        {
-            log::info!("Writing texture.");
-        }
         queue.write_texture(
             wgpu::ImageCopyTexture {
                 texture: &texture,
@@ -148,60 +302,113 @@ impl Texture {
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
             },
-            match bits_per_pixel {
-                3 => &temp,
-                4 => &buffer,
-                _ => panic!("Bits size of {} is not supported", bits_per_pixel),
-            },
+            &data,
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: Some(width * 4), // now only 4 bits per pixel is supported,
-                rows_per_image: Some(height),
+                bytes_per_row: Some(image.width * format.block_size(None).unwrap()),
+                rows_per_image: Some(image.height),
             },
             texture_extent,
         );
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor {
-            label: None,
-            format: Some(sc_desc.format),
-            dimension: Some(wgpu::TextureViewDimension::D2),
-            aspect: wgpu::TextureAspect::All,
-            base_mip_level: 0,
-            mip_level_count: Some(1),
-            base_array_layer: 0,
-            array_layer_count: Some(1),
+            dimension: Some(view_dimension),
+            ..Default::default()
         });
 
-        // let texture_type = TextureType::Diffuse;
-
-        let width = texture_extent.width;
-        let height = texture_extent.height;
-        let depth = texture_extent.depth_or_array_layers;
-
-        #[cfg(feature = "texture_debug")]
-        {
-            log::info!("Texture created.");
-        }
-
-        Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
 
+        Ok(Self {
             texture,
             view,
             sampler,
-            width,
-            height,
-            depth,
+            width: image.width,
+            height: image.height,
+            depth: image.depth,
+        })
+    }
+
+    /// Creates a float texture from a Radiance .hdr file (e.g. an environment map).
+    pub fn from_hdr_bytes(device: &wgpu::Device, queue: &wgpu::Queue, bytes: &[u8], format: wgpu::TextureFormat, label: Option<&str>) -> Result<Self, ImageError> {
+        Self::from_float_image(device, queue, &decode_hdr(bytes)?, format, label)
+    }
+
+    /// Creates a float texture from a .npy array. See decode_npy for the supported shapes.
+    pub fn from_npy_bytes(device: &wgpu::Device, queue: &wgpu::Queue, bytes: &[u8], format: wgpu::TextureFormat, label: Option<&str>) -> Result<Self, ImageError> {
+        Self::from_float_image(device, queue, &decode_npy(bytes)?, format, label)
+    }
+
+    /// Creates a 3d texture (see create_texture3d) from a raw volume.
+    pub fn from_raw_volume(device: &wgpu::Device, queue: &wgpu::Queue, volume: &RawVolume) -> Self {
+
+        let texture = Self::create_texture3d(device, &volume.format, volume.width, volume.height, volume.depth);
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &volume.data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(volume.width * volume.format.block_size(None).unwrap()),
+                rows_per_image: Some(volume.height),
+            },
+            wgpu::Extent3d {
+                width: volume.width,
+                height: volume.height,
+                depth_or_array_layers: volume.depth,
+            },
+        );
+
+        texture
+    }
+
+    /// Copy mip level 0 of a float texture back to a FloatImage (e.g. for encode_hdr or
+    /// encode_npy).
+    pub async fn to_float_image(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<FloatImage, ImageError> {
+        let data = self.to_vec::<u8>(device, queue).await;
+        FloatImage::from_texel_data(self.width, self.height, self.depth, self.texture.format(), &data)
+    }
+
+    /// Copy mip level 0 of the texture back to a raw volume.
+    pub async fn to_raw_volume(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> RawVolume {
+        RawVolume {
+            width: self.width,
+            height: self.height,
+            depth: self.depth,
+            format: self.texture.format(),
+            data: self.to_vec::<u8>(device, queue).await,
         }
     }
 
-    /// Create a texture without data.
-    /// TODO: create storage texture (a boolean parameter).
+    /// A builder for 1d, 2d, 3d, array and cube textures. See TextureBuilder.
+    pub fn builder<'a>(kind: TextureKind, format: wgpu::TextureFormat, width: u32, height: u32, depth_or_layers: u32) -> TextureBuilder<'a> {
+        TextureBuilder::new(kind, format, width, height, depth_or_layers)
+    }
+
+    /// Create a texture without data. If mipmaps is true, the texture has a full mip chain
+    /// that can be filled with generate_mipmaps after writing level 0.
+    /// Use TextureBuilder for storage textures.
     /// TODO: debugging information.
     pub fn create_texture2d(device: &wgpu::Device,
                             sc_desc: &wgpu::SurfaceConfiguration,
                             sample_count: u32,
                             width: u32,
-                            height: u32) -> Self {
+                            height: u32,
+                            mipmaps: bool) -> Self {
+
+        assert!(!mipmaps || sample_count == 1, "create_texture2d: multisampled textures can't have mipmaps.");
 
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::MirrorRepeat,
@@ -222,14 +429,20 @@ impl Texture {
             depth_or_array_layers: 1,
         };
 
+        let (mip_levels, mip_usage) = match mipmaps {
+            true => (mip_level_count(width, height), wgpu::TextureUsages::RENDER_ATTACHMENT),
+            false => (1, wgpu::TextureUsages::empty()),
+        };
+
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             size: texture_extent,
-            mip_level_count: 1,
+            mip_level_count: mip_levels,
             sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: sc_desc.format,
             usage: wgpu::TextureUsages::TEXTURE_BINDING |
-                   wgpu::TextureUsages::COPY_DST,
+                   wgpu::TextureUsages::COPY_DST |
+                   mip_usage,
             label: None,
             view_formats: &[],
         });
@@ -240,7 +453,7 @@ impl Texture {
             dimension: Some(wgpu::TextureViewDimension::D2),
             aspect: wgpu::TextureAspect::All,
             base_mip_level: 0,
-            mip_level_count: Some(1),
+            mip_level_count: None,
             base_array_layer: 0,
             array_layer_count: Some(1),
         });
@@ -314,51 +527,74 @@ impl Texture {
         }
     }
 
-    /// Copy the content of the texture to vector.
-    pub async fn to_vec<T: Convert2Vec>(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<T> {
+    /// Copy the content of mip level 0 (all layers) to a vector. The texel size is taken
+    /// from the texture format.
+    pub async fn to_vec<T: Pod>(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<T> {
+        self.read_region(device, queue, &TextureRegion::default()).await
+    }
+
+    /// Copy a region of the texture to a vector. The rows are tightly packed (texel blocks for
+    /// compressed formats), images (depth slices or array layers) follow each other.
+    pub async fn read_region<T: Pod>(&self, device: &wgpu::Device, queue: &wgpu::Queue, region: &TextureRegion) -> Vec<T> {
+
+        let format = self.texture.format();
+        let dimension = self.texture.dimension();
+
+        assert!(region.mip_level < self.texture.mip_level_count(),
+                "Texture::read_region: mip level {} doesn't exist.", region.mip_level);
+
+        // The whole mip level from origin if the size isn't given.
+        let level_size = self.texture.size().mip_level_size(region.mip_level, dimension).physical_size(format);
+        let size = region.size.unwrap_or(wgpu::Extent3d {
+            width: level_size.width.saturating_sub(region.origin.x),
+            height: level_size.height.saturating_sub(region.origin.y),
+            depth_or_array_layers: level_size.depth_or_array_layers.saturating_sub(region.origin.z),
+        });
+        assert!(region.origin.x + size.width <= level_size.width &&
+                region.origin.y + size.height <= level_size.height &&
+                region.origin.z + size.depth_or_array_layers <= level_size.depth_or_array_layers &&
+                size.width > 0 && size.height > 0 && size.depth_or_array_layers > 0,
+                "Texture::read_region: the region {:?} {:?} is out of the mip level {:?}.", region.origin, size, level_size);
+
+        let layout = copy_layout(format, region.aspect, size);
 
-        let size = (self.width * self.height * self.depth * 4) as u64;
-        
         let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size, 
+            label: Some("Texture readback buffer"),
+            size: layout.padded_size(),
             usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
 
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Texture readback encoder") });
 
         encoder.copy_texture_to_buffer(
             wgpu::ImageCopyTexture {
                 texture: &self.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
+                mip_level: region.mip_level,
+                origin: region.origin,
+                aspect: region.aspect,
             },
             wgpu::ImageCopyBuffer {
                 buffer: &staging_buffer,
                 layout: wgpu::ImageDataLayout {
                     offset: 0,
-                    bytes_per_row: Some(self.width * 4), 
-                    rows_per_image: Some(self.depth),
+                    bytes_per_row: Some(layout.padded_bytes_per_row),
+                    rows_per_image: Some(layout.rows_per_image),
                 },
             },
-            wgpu::Extent3d {
-                width: self.width,
-                height: self.height,
-                depth_or_array_layers: self.depth,
-            },
+            size,
         );
         queue.submit(Some(encoder.finish()));
 
         let buffer_slice = staging_buffer.slice(..);
-        buffer_slice.map_async(wgpu::MapMode::Read, |_| ());
-        device.poll(wgpu::Maintain::Wait);
+        map_read(device, buffer_slice).await.expect("Failed to map the staging buffer.");
 
-        let res: Vec<T>;
+        let res: Vec<T> = {
+            let data = buffer_slice.get_mapped_range();
+            vec_from_bytes(&strip_row_padding(&data, &layout))
+        };
+        staging_buffer.unmap();
 
-        let data = buffer_slice.get_mapped_range();
-        res = Convert2Vec::convert(&data);
         res
     }
 
@@ -441,3 +677,386 @@ impl Texture {
         }
     }
 }
+
+/// The dimension and the view dimension of a texture created with TextureBuilder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureKind {
+    D1,
+    D2,
+    D2Array,
+    /// 6 layers.
+    Cube,
+    /// A multiple of 6 layers.
+    CubeArray,
+    D3,
+}
+
+impl TextureKind {
+
+    pub fn dimension(&self) -> wgpu::TextureDimension {
+        match self {
+            TextureKind::D1 => wgpu::TextureDimension::D1,
+            TextureKind::D3 => wgpu::TextureDimension::D3,
+            _ => wgpu::TextureDimension::D2,
+        }
+    }
+
+    pub fn view_dimension(&self) -> wgpu::TextureViewDimension {
+        match self {
+            TextureKind::D1 => wgpu::TextureViewDimension::D1,
+            TextureKind::D2 => wgpu::TextureViewDimension::D2,
+            TextureKind::D2Array => wgpu::TextureViewDimension::D2Array,
+            TextureKind::Cube => wgpu::TextureViewDimension::Cube,
+            TextureKind::CubeArray => wgpu::TextureViewDimension::CubeArray,
+            TextureKind::D3 => wgpu::TextureViewDimension::D3,
+        }
+    }
+}
+
+/// Configures and creates a Texture and the matching bind group layout entries.
+///
+/// let builder = Texture::builder(TextureKind::D3, wgpu::TextureFormat::R32Float, 64, 64, 64)
+///     .with_storage()
+///     .with_label("fim volume");
+/// let texture = builder.build(device);
+/// let entry = builder.storage_layout_entry(0, wgpu::ShaderStages::COMPUTE, wgpu::StorageTextureAccess::ReadWrite);
+///
+/// The default usage is TEXTURE_BINDING | COPY_DST with one mip level and one sample.
+/// ReadWrite storage access needs a format that supports it (e.g. R32Float with
+/// wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES).
+#[derive(Clone, Debug)]
+pub struct TextureBuilder<'a> {
+    label: Option<&'a str>,
+    kind: TextureKind,
+    format: wgpu::TextureFormat,
+    size: wgpu::Extent3d,
+    usage: wgpu::TextureUsages,
+    mip_level_count: u32,
+    sample_count: u32,
+    filter: wgpu::FilterMode,
+    address_mode: wgpu::AddressMode,
+}
+
+impl<'a> TextureBuilder<'a> {
+
+    /// depth_or_layers is the depth of 3d textures and the number of layers of array and cube
+    /// textures. It must be 1 for D1 and D2 textures.
+    pub fn new(kind: TextureKind, format: wgpu::TextureFormat, width: u32, height: u32, depth_or_layers: u32) -> Self {
+        Self {
+            label: None,
+            kind,
+            format,
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: depth_or_layers },
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            mip_level_count: 1,
+            sample_count: 1,
+            filter: wgpu::FilterMode::Linear,
+            address_mode: wgpu::AddressMode::ClampToEdge,
+        }
+    }
+
+    pub fn with_label(mut self, label: &'a str) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    /// Replace the usage flags.
+    pub fn with_usage(mut self, usage: wgpu::TextureUsages) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    pub fn with_storage(mut self) -> Self {
+        self.usage |= wgpu::TextureUsages::STORAGE_BINDING;
+        self
+    }
+
+    pub fn with_render_attachment(mut self) -> Self {
+        self.usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        self
+    }
+
+    pub fn with_copy_src(mut self) -> Self {
+        self.usage |= wgpu::TextureUsages::COPY_SRC;
+        self
+    }
+
+    pub fn with_copy_dst(mut self) -> Self {
+        self.usage |= wgpu::TextureUsages::COPY_DST;
+        self
+    }
+
+    pub fn with_mip_level_count(mut self, mip_level_count: u32) -> Self {
+        self.mip_level_count = mip_level_count;
+        self
+    }
+
+    /// A full mip chain. Add with_render_attachment to use Texture::generate_mipmaps.
+    pub fn with_mipmaps(mut self) -> Self {
+        self.mip_level_count = match self.kind {
+            TextureKind::D3 => mip_level_count(self.size.width, self.size.height).max(mip_level_count(self.size.depth_or_array_layers, 1)),
+            TextureKind::D1 => 1,
+            _ => mip_level_count(self.size.width, self.size.height),
+        };
+        self
+    }
+
+    pub fn with_sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    /// The mag, min and mipmap filter of the sampler.
+    pub fn with_filter(mut self, filter: wgpu::FilterMode) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_address_mode(mut self, address_mode: wgpu::AddressMode) -> Self {
+        self.address_mode = address_mode;
+        self
+    }
+
+    /// Create the texture, a view and a sampler. The view covers all layers and all mip levels,
+    /// or only level 0 with with_storage, since a storage binding can't have several levels.
+    pub fn build(&self, device: &wgpu::Device) -> Texture {
+
+        match self.kind {
+            TextureKind::D1 | TextureKind::D2 => assert!(self.size.depth_or_array_layers == 1, "TextureBuilder: {:?} textures have one layer.", self.kind),
+            TextureKind::Cube => assert!(self.size.depth_or_array_layers == 6, "TextureBuilder: cube textures have 6 layers."),
+            TextureKind::CubeArray => assert!(self.size.depth_or_array_layers % 6 == 0, "TextureBuilder: cube arrays need a multiple of 6 layers."),
+            _ => {}
+        }
+        assert!(self.sample_count == 1 || self.mip_level_count == 1, "TextureBuilder: multisampled textures can't have mipmaps.");
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: self.label,
+            size: self.size,
+            mip_level_count: self.mip_level_count,
+            sample_count: self.sample_count,
+            dimension: self.kind.dimension(),
+            format: self.format,
+            usage: self.usage,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: self.label,
+            dimension: Some(self.kind.view_dimension()),
+            mip_level_count: self.view_mip_level_count(),
+            ..Default::default()
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: self.label,
+            address_mode_u: self.address_mode,
+            address_mode_v: self.address_mode,
+            address_mode_w: self.address_mode,
+            mag_filter: self.filter,
+            min_filter: self.filter,
+            mipmap_filter: self.filter,
+            ..Default::default()
+        });
+
+        Texture {
+            texture,
+            view,
+            sampler,
+            width: self.size.width,
+            height: self.size.height,
+            depth: self.size.depth_or_array_layers,
+        }
+    }
+
+    /// The mip level count of the view of build.
+    fn view_mip_level_count(&self) -> Option<u32> {
+        self.usage.contains(wgpu::TextureUsages::STORAGE_BINDING).then_some(1)
+    }
+
+    /// A layout entry for sampling the texture (texture_1d/2d/3d/cube<T> in wgsl). The sample
+    /// type is taken from the format. Multisampled float textures aren't filterable.
+    pub fn sampled_layout_entry(&self, binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+        let sample_type = match self.format.sample_type(None) {
+            Some(wgpu::TextureSampleType::Float { .. }) if self.sample_count > 1 => wgpu::TextureSampleType::Float { filterable: false },
+            Some(sample_type) => sample_type,
+            None => panic!("TextureBuilder: the format {:?} can't be sampled without an aspect.", self.format),
+        };
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: self.kind.view_dimension(),
+                multisampled: self.sample_count > 1,
+            },
+            count: None,
+        }
+    }
+
+    /// A layout entry for storage access (texture_storage_1d/2d/2d_array/3d in wgsl).
+    pub fn storage_layout_entry(&self, binding: u32, visibility: wgpu::ShaderStages, access: wgpu::StorageTextureAccess) -> wgpu::BindGroupLayoutEntry {
+        assert!(self.usage.contains(wgpu::TextureUsages::STORAGE_BINDING), "TextureBuilder: add with_storage for storage access.");
+        assert!(!matches!(self.kind, TextureKind::Cube | TextureKind::CubeArray), "TextureBuilder: cube textures can't be storage textures.");
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::StorageTexture {
+                access,
+                format: self.format,
+                view_dimension: self.kind.view_dimension(),
+            },
+            count: None,
+        }
+    }
+}
+
+/// A region of a texture for Texture::read_region. The default is the whole mip level 0.
+#[derive(Clone, Copy, Debug)]
+pub struct TextureRegion {
+    pub mip_level: u32,
+    /// The first texel. z is the first depth slice or array layer.
+    pub origin: wgpu::Origin3d,
+    /// The size in texels. None means the rest of the mip level from origin. Must be a multiple
+    /// of the block dimensions for compressed formats.
+    pub size: Option<wgpu::Extent3d>,
+    pub aspect: wgpu::TextureAspect,
+}
+
+impl Default for TextureRegion {
+    fn default() -> Self {
+        Self {
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            size: None,
+            aspect: wgpu::TextureAspect::All,
+        }
+    }
+}
+
+/// The buffer layout of a texture to buffer copy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct CopyLayout {
+    /// The bytes of one row of texel blocks.
+    bytes_per_row: u32,
+    /// bytes_per_row aligned to COPY_BYTES_PER_ROW_ALIGNMENT.
+    padded_bytes_per_row: u32,
+    /// The number of block rows in one image.
+    rows_per_image: u32,
+    /// The number of depth slices or array layers.
+    images: u32,
+}
+
+impl CopyLayout {
+    fn padded_size(&self) -> wgpu::BufferAddress {
+        self.padded_bytes_per_row as wgpu::BufferAddress * self.rows_per_image as wgpu::BufferAddress * self.images as wgpu::BufferAddress
+    }
+}
+
+/// Compute the copy layout from the block size and the block dimensions of the format.
+fn copy_layout(format: wgpu::TextureFormat, aspect: wgpu::TextureAspect, size: wgpu::Extent3d) -> CopyLayout {
+
+    let block_aspect = match aspect {
+        wgpu::TextureAspect::All => None,
+        a => Some(a),
+    };
+    let block_size = format.block_size(block_aspect)
+        .unwrap_or_else(|| panic!("The format {:?} (aspect {:?}) can't be copied to a buffer.", format, aspect));
+    let (block_width, block_height) = format.block_dimensions();
+
+    let bytes_per_row = (size.width + block_width - 1) / block_width * block_size;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    CopyLayout {
+        bytes_per_row,
+        padded_bytes_per_row: (bytes_per_row + align - 1) / align * align,
+        rows_per_image: (size.height + block_height - 1) / block_height,
+        images: size.depth_or_array_layers,
+    }
+}
+
+/// Remove the row padding of the copied data.
+fn strip_row_padding(data: &[u8], layout: &CopyLayout) -> Vec<u8> {
+    let rows = (layout.rows_per_image * layout.images) as usize;
+    let mut unpadded: Vec<u8> = Vec::with_capacity(layout.bytes_per_row as usize * rows);
+    for row in data.chunks_exact(layout.padded_bytes_per_row as usize).take(rows) {
+        unpadded.extend_from_slice(&row[..layout.bytes_per_row as usize]);
+    }
+    unpadded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_layouts() {
+        let size = wgpu::Extent3d { width: 3, height: 5, depth_or_array_layers: 2 };
+
+        let layout = copy_layout(wgpu::TextureFormat::Rgba32Float, wgpu::TextureAspect::All, size);
+        assert_eq!(layout, CopyLayout { bytes_per_row: 48, padded_bytes_per_row: 256, rows_per_image: 5, images: 2 });
+        assert_eq!(layout.padded_size(), 256 * 5 * 2);
+
+        // 4x4 blocks of 8 bytes.
+        let layout = copy_layout(wgpu::TextureFormat::Bc1RgbaUnorm, wgpu::TextureAspect::All, size);
+        assert_eq!(layout, CopyLayout { bytes_per_row: 8, padded_bytes_per_row: 256, rows_per_image: 2, images: 2 });
+
+        let layout = copy_layout(wgpu::TextureFormat::Depth24PlusStencil8, wgpu::TextureAspect::StencilOnly, size);
+        assert_eq!(layout.bytes_per_row, 3);
+    }
+
+    #[test]
+    fn strip_padding() {
+        let layout = CopyLayout { bytes_per_row: 2, padded_bytes_per_row: 4, rows_per_image: 2, images: 1 };
+        assert_eq!(strip_row_padding(&[1, 2, 0, 0, 3, 4, 0, 0], &layout), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn texture_kinds() {
+        use wgpu::{TextureDimension as D, TextureViewDimension as V};
+        let kinds = [
+            (TextureKind::D1, D::D1, V::D1),
+            (TextureKind::D2, D::D2, V::D2),
+            (TextureKind::D2Array, D::D2, V::D2Array),
+            (TextureKind::Cube, D::D2, V::Cube),
+            (TextureKind::CubeArray, D::D2, V::CubeArray),
+            (TextureKind::D3, D::D3, V::D3),
+        ];
+        for (kind, dimension, view_dimension) in kinds {
+            assert_eq!(kind.dimension(), dimension);
+            assert_eq!(kind.view_dimension(), view_dimension);
+        }
+    }
+
+    #[test]
+    fn layout_entries() {
+        let stages = wgpu::ShaderStages::FRAGMENT;
+        let sampled = |builder: TextureBuilder| builder.sampled_layout_entry(0, stages).ty;
+
+        let builder = Texture::builder(TextureKind::Cube, wgpu::TextureFormat::Rgba8Unorm, 4, 4, 6);
+        assert_eq!(sampled(builder), wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::Cube,
+            multisampled: false,
+        });
+
+        let builder = Texture::builder(TextureKind::D2, wgpu::TextureFormat::Rgba8Unorm, 4, 4, 1).with_sample_count(4);
+        assert_eq!(sampled(builder), wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: true,
+        });
+
+        let builder = Texture::builder(TextureKind::D2, wgpu::TextureFormat::R32Uint, 4, 4, 1);
+        assert!(matches!(sampled(builder), wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Uint, .. }));
+
+        let builder = Texture::builder(TextureKind::D3, wgpu::TextureFormat::R32Float, 4, 4, 4).with_storage().with_mipmaps();
+        assert_eq!(builder.storage_layout_entry(1, stages, wgpu::StorageTextureAccess::WriteOnly).ty, wgpu::BindingType::StorageTexture {
+            access: wgpu::StorageTextureAccess::WriteOnly,
+            format: wgpu::TextureFormat::R32Float,
+            view_dimension: wgpu::TextureViewDimension::D3,
+        });
+        assert_eq!(builder.view_mip_level_count(), Some(1));
+        let builder = Texture::builder(TextureKind::D2, wgpu::TextureFormat::R32Float, 4, 4, 1).with_mipmaps();
+        assert_eq!(builder.view_mip_level_count(), None);
+    }
+}
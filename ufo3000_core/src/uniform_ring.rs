@@ -0,0 +1,121 @@
+use bytemuck::Pod;
+
+/// Hands out aligned offsets from a fixed size range. Reset once per frame.
+#[derive(Clone, Debug)]
+struct RingAllocator {
+    size: wgpu::BufferAddress,
+    alignment: wgpu::BufferAddress,
+    head: wgpu::BufferAddress,
+}
+
+impl RingAllocator {
+
+    /// The offset for size bytes or None if the ring is full.
+    fn allocate(&mut self, size: wgpu::BufferAddress) -> Option<wgpu::BufferAddress> {
+        let offset = (self.head + self.alignment - 1) / self.alignment * self.alignment;
+        if offset + size > self.size {
+            return None;
+        }
+        self.head = offset + size;
+        Some(offset)
+    }
+}
+
+/// A large uniform buffer that is sub-allocated for per-draw and per-dispatch constants. Each
+/// push writes the data to the next chunk aligned to min_uniform_buffer_offset_alignment and
+/// returns the dynamic offset for set_bind_group. Call reset at the beginning of every frame.
+///
+/// The bind group layout entry must have has_dynamic_offset set (see layout_entry) and the
+/// bind group must use binding::<T>.
+pub struct UniformRing {
+    buffer: wgpu::Buffer,
+    allocator: RingAllocator,
+}
+
+impl UniformRing {
+
+    /// Create a ring of size bytes.
+    pub fn init(device: &wgpu::Device, size: wgpu::BufferAddress, label: wgpu::Label) -> Self {
+
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress;
+        assert!(size >= alignment, "UniformRing: size must be at least {} bytes.", alignment);
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label,
+            size,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            buffer,
+            allocator: RingAllocator { size, alignment, head: 0 },
+        }
+    }
+
+    /// Write the value to the next chunk and return its dynamic offset.
+    pub fn push<T: Pod>(&mut self, queue: &wgpu::Queue, value: &T) -> u32 {
+        self.push_bytes(queue, bytemuck::bytes_of(value))
+    }
+
+    /// Write the data to the next chunk and return its dynamic offset.
+    pub fn push_bytes(&mut self, queue: &wgpu::Queue, data: &[u8]) -> u32 {
+        let offset = self.allocator.allocate(data.len() as wgpu::BufferAddress)
+            .unwrap_or_else(|| panic!("UniformRing is full ({} bytes). Create a larger ring or call reset every frame.", self.allocator.size));
+        queue.write_buffer(&self.buffer, offset, data);
+        offset as u32
+    }
+
+    /// Start over from the beginning of the buffer. The previous frame must have been submitted.
+    pub fn reset(&mut self) {
+        self.allocator.head = 0;
+    }
+
+    /// The number of used bytes.
+    pub fn used(&self) -> wgpu::BufferAddress {
+        self.allocator.head
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// The binding resource for a chunk of type T. The offset comes from the dynamic offset.
+    pub fn binding<T: Pod>(&self) -> wgpu::BindingResource {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset: 0,
+            size: wgpu::BufferSize::new(std::mem::size_of::<T>() as wgpu::BufferAddress),
+        })
+    }
+
+    /// A uniform bind group layout entry with a dynamic offset for chunks of type T.
+    pub fn layout_entry<T: Pod>(binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: true,
+                min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<T>() as wgpu::BufferAddress),
+            },
+            count: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligned_allocations() {
+        let mut ring = RingAllocator { size: 1024, alignment: 256, head: 0 };
+        assert_eq!(ring.allocate(64), Some(0));
+        assert_eq!(ring.allocate(64), Some(256));
+        assert_eq!(ring.allocate(300), Some(512));
+        assert_eq!(ring.allocate(64), None);
+        ring.head = 0;
+        assert_eq!(ring.allocate(1024), Some(0));
+    }
+}
@@ -0,0 +1,83 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::path::PathBuf;
+use ufo3000_core::golden::{check_golden, GoldenError, GoldenOptions};
+use ufo3000_core::input::InputCache;
+use ufo3000_core::render_object::create_render_pass;
+use ufo3000_core::screen::ScreenTexture;
+use ufo3000_core::template::{
+    WGPUConfiguration,
+    WGPUFeatures,
+    Application,
+    Spawner,
+    RenderTarget,
+    SetupError,
+};
+
+struct GoldenFeatures {}
+
+impl WGPUFeatures for GoldenFeatures {}
+
+/// An application that clears the screen with blue color.
+struct ClearApp {
+    screen: ScreenTexture,
+}
+
+impl Application for ClearApp {
+
+    fn init(configuration: &WGPUConfiguration) -> Self {
+        Self {
+            screen: ScreenTexture::init(&configuration.device, &configuration.sc_desc, true),
+        }
+    }
+
+    fn render(&mut self,
+              device: &wgpu::Device,
+              queue: &mut wgpu::Queue,
+              target: &RenderTarget,
+              sc_desc: &wgpu::SurfaceConfiguration,
+              _spawner: &Spawner) {
+
+        assert!(self.screen.acquire_screen_texture(device, sc_desc, target));
+
+        let view = self.screen.view.as_ref().unwrap();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Clear encoder") });
+        {
+            let _render_pass = create_render_pass(
+                &mut encoder,
+                view,
+                self.screen.depth_texture.as_ref(),
+                true,
+                &Some(wgpu::Color { r: 0.0, g: 0.0, b: 1.0, a: 1.0 }),
+                None
+            );
+        }
+        queue.submit(Some(encoder.finish()));
+
+        self.screen.prepare_for_rendering();
+    }
+
+    fn input(&mut self, _queue: &wgpu::Queue, _input: &InputCache) { }
+
+    fn resize(&mut self, _device: &wgpu::Device, _sc_desc: &wgpu::SurfaceConfiguration, _new_size: winit::dpi::PhysicalSize<u32>) { }
+
+    fn update(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue, _input: &InputCache, _spawner: &Spawner) { }
+
+    fn exit(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue, _input: &InputCache, _spawner: &Spawner) { }
+}
+
+// The golden tests render on the fallback (software) adapter, so they run with a plain
+// `cargo test`. They are skipped only if there is no fallback adapter, any other setup
+// failure is an error.
+#[test]
+fn clear_blue() {
+
+    let golden = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden/clear_blue.png");
+    let options = GoldenOptions { frames: 2, force_fallback_adapter: true, ..GoldenOptions::default() };
+
+    match check_golden::<ClearApp, GoldenFeatures>(&golden, 64, 64, &options) {
+        Ok(()) => {}
+        Err(GoldenError::Setup(SetupError::NoAdapter)) => eprintln!("Skipping clear_blue: no fallback adapter."),
+        Err(e) => panic!("{}", e),
+    }
+}
@@ -0,0 +1,258 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+// Headless tests that need a gpu adapter. Most are ignored by default and run with
+// `cargo test -- --ignored`, a setup failure is an error, not a skip. The tests that use the
+// fallback (software) adapter run by default and are skipped only if there is none.
+
+use ufo3000_core::buffer::buffer_from_data;
+use ufo3000_core::gpu_buffer::GpuBuffer;
+use ufo3000_core::mipmap::{MipFilter, MipmapGenerator};
+use ufo3000_core::ping_pong::{PingPong, PingPongBinding};
+use ufo3000_core::profiler::GpuProfiler;
+use ufo3000_core::render_object::{ComputeObject, DrawIndirect, RenderPassBuilder, RenderPipelineBuilder, multi_draw_indirect};
+use ufo3000_core::template::{WGPUFeatures, HeadlessConfiguration, SetupError, setup_headless_blocking};
+use ufo3000_core::texture::{Texture, TextureKind, TextureRegion};
+
+struct GpuTestFeatures {}
+
+impl WGPUFeatures for GpuTestFeatures {}
+
+fn headless() -> HeadlessConfiguration {
+    match setup_headless_blocking::<GpuTestFeatures>(false) {
+        Ok(configuration) => configuration,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+/// The fallback adapter or None if there is none.
+fn fallback() -> Option<HeadlessConfiguration> {
+    match setup_headless_blocking::<GpuTestFeatures>(true) {
+        Ok(configuration) => Some(configuration),
+        Err(SetupError::NoAdapter) => None,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[test]
+#[ignore = "needs a gpu adapter, run with --ignored"]
+fn profiler_without_timestamp_query() {
+
+    // GpuTestFeatures doesn't request TIMESTAMP_QUERY.
+    let HeadlessConfiguration { device, queue, .. } = headless();
+    let mut profiler = GpuProfiler::init(&device, &queue, 4);
+    assert!(!profiler.is_enabled());
+
+    let scope = profiler.scope("pass");
+    assert!(scope.compute_timestamp_writes().is_none());
+    assert!(scope.render_timestamp_writes().is_none());
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Profiler test encoder") });
+    profiler.resolve(&mut encoder);
+    queue.submit(Some(encoder.finish()));
+    profiler.end_frame();
+    assert!(!profiler.poll_results(&device));
+    assert!(profiler.timings().is_empty());
+}
+
+const ADD_SHADER: &str = r#"
+@group(0) @binding(0) var<storage, read> src: array<u32>;
+@group(0) @binding(1) var<storage, read_write> dst: array<u32>;
+
+@compute @workgroup_size(4)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    dst[id.x] = src[id.x] + 10u;
+}
+"#;
+
+fn storage_entry(binding: u32, read_only: bool) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+#[test]
+#[ignore = "needs a gpu adapter, run with --ignored"]
+fn ping_pong_alternates() {
+
+    let HeadlessConfiguration { device, queue, .. } = headless();
+
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Ping pong test shader"),
+        source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(ADD_SHADER)),
+    });
+    let compute_object = ComputeObject::init(
+        &device,
+        &module,
+        Some("Ping pong test"),
+        &vec![vec![storage_entry(0, true), storage_entry(1, false)]],
+        &"main".to_string(),
+        None,
+    );
+
+    let usage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST;
+    let mut ping_pong = PingPong::init(
+        &device,
+        &compute_object,
+        [GpuBuffer::from_data(&device, &[1u32, 2, 3, 4], usage, Some("ping")),
+         GpuBuffer::from_data(&device, &[0u32; 4], usage, Some("pong"))],
+        &vec![vec![PingPongBinding::Read, PingPongBinding::Write]],
+    );
+
+    // Each step reads current, writes previous and swaps.
+    let mut results = Vec::new();
+    for _ in 0..2 {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Ping pong test encoder") });
+        compute_object.dispatch(ping_pong.bind_groups(), &[], &mut encoder, 1, 1, 1, Some("Ping pong step"), None);
+        queue.submit(Some(encoder.finish()));
+        ping_pong.swap();
+
+        let current = pollster::block_on(ping_pong.current().to_vec(&device, &queue));
+        let previous = pollster::block_on(ping_pong.previous().to_vec(&device, &queue));
+        results.push((current, previous));
+    }
+
+    // Step 1 reads ping and writes pong, step 2 reads pong and writes ping.
+    assert_eq!(results[0], (vec![11, 12, 13, 14], vec![1, 2, 3, 4]));
+    assert_eq!(results[1], (vec![21, 22, 23, 24], vec![11, 12, 13, 14]));
+}
+
+/// Fill level 0 of a width x height Rgba8Unorm texture with the gray values texel(x, y),
+/// generate the box filtered mip chain and return the red channel of every level.
+fn box_mip_chain(device: &wgpu::Device,
+                 queue: &wgpu::Queue,
+                 generator: &mut MipmapGenerator,
+                 width: u32,
+                 height: u32,
+                 texel: impl Fn(u32, u32) -> u8) -> Vec<Vec<u8>> {
+
+    let texture = Texture::builder(TextureKind::D2, wgpu::TextureFormat::Rgba8Unorm, width, height, 1)
+        .with_mipmaps()
+        .with_render_attachment()
+        .with_copy_src()
+        .build(device);
+
+    let data: Vec<u8> = (0..height).flat_map(|y| (0..width).flat_map(|x| {
+        let v = texel(x, y);
+        [v, v, v, 255]
+    }).collect::<Vec<u8>>()).collect();
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture.texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        &data,
+        wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(width * 4), rows_per_image: Some(height) },
+        wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+    );
+
+    texture.generate_mipmaps_with(generator, device, queue, MipFilter::Box);
+
+    (0..texture.texture.mip_level_count()).map(|mip_level| {
+        let region = TextureRegion { mip_level, ..TextureRegion::default() };
+        let texels: Vec<u8> = pollster::block_on(texture.read_region(device, queue, &region));
+        texels.chunks_exact(4).map(|t| t[0]).collect()
+    }).collect()
+}
+
+#[test]
+#[ignore = "needs a gpu adapter, run with --ignored"]
+fn box_filter_mipmaps() {
+
+    let HeadlessConfiguration { device, queue, .. } = headless();
+    let mut generator = MipmapGenerator::init(&device);
+
+    // The texel (x, y) is 8x + 32y, so every 2x2 average is exact in 8 bits.
+    let levels = box_mip_chain(&device, &queue, &mut generator, 4, 4, |x, y| (8 * x + 32 * y) as u8);
+    assert_eq!(levels[1..], [vec![20, 36, 84, 100], vec![60]]);
+
+    // 5x3 -> 2x1 -> 1x1. Each texel of level 1 covers 2.5x3 texels, so the averages of the
+    // linear ramp 20x + 30y are the ramp at the centers (0.8, 1) and (3.2, 1). The last column
+    // must be read, otherwise the chain is shifted to the left.
+    let levels = box_mip_chain(&device, &queue, &mut generator, 5, 3, |x, y| (20 * x + 30 * y) as u8);
+    let expected = [vec![46, 94], vec![70]];
+    for (level, expected) in levels[1..].iter().zip(expected.iter()) {
+        assert_eq!(level.len(), expected.len());
+        for (a, b) in level.iter().zip(expected.iter()) {
+            assert!(a.abs_diff(*b) <= 1, "{:?} != {:?}", level, expected);
+        }
+    }
+}
+
+const POINT_SHADER: &str = r#"
+@vertex
+fn vs_main(@location(0) position: vec2<f32>) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(position, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0);
+}
+"#;
+
+#[test]
+fn multi_draw_loop_fallback() {
+
+    // GpuTestFeatures doesn't request the multi draw features, so the commands are drawn with
+    // a loop of draw_indirect calls.
+    let HeadlessConfiguration { device, queue, .. } = match fallback() {
+        Some(configuration) => configuration,
+        None => {
+            eprintln!("Skipping multi_draw_loop_fallback: no fallback adapter.");
+            return;
+        }
+    };
+    assert!(!device.features().contains(wgpu::Features::MULTI_DRAW_INDIRECT));
+
+    let format = wgpu::TextureFormat::Rgba8Unorm;
+    let target = Texture::builder(TextureKind::D2, format, 4, 1, 1)
+        .with_render_attachment()
+        .with_copy_src()
+        .build(&device);
+
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Multi draw test shader"),
+        source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(POINT_SHADER)),
+    });
+    let render_object = RenderPipelineBuilder::new(format)
+        .with_vertex_buffer(&[wgpu::VertexFormat::Float32x2], wgpu::VertexStepMode::Vertex)
+        .with_topology(wgpu::PrimitiveTopology::PointList)
+        .with_write_mask(wgpu::ColorWrites::ALL)
+        .with_depth_stencil(None)
+        .build(&device, &module, &vec![], Some("Multi draw test pipeline"));
+
+    // One point at the center of each texel.
+    let points: [[f32; 2]; 4] = [[-0.75, 0.0], [-0.25, 0.0], [0.25, 0.0], [0.75, 0.0]];
+    let draw_buffer = buffer_from_data(&device, &points, wgpu::BufferUsages::VERTEX, Some("Multi draw test points"));
+
+    // The third command is unused. The count buffer asks for one command, but it is ignored
+    // without MULTI_DRAW_INDIRECT_COUNT and all the four commands are drawn.
+    let command = |instance_count, base_vertex| DrawIndirect { vertex_count: 1, instance_count, base_vertex, base_instance: 0 };
+    let commands = [command(1, 0), command(1, 1), command(0, 2), command(1, 3)];
+    let indirect_buffer = buffer_from_data(&device, &commands, wgpu::BufferUsages::INDIRECT, Some("Multi draw test commands"));
+    let count_buffer = buffer_from_data(&device, &[1u32], wgpu::BufferUsages::INDIRECT, Some("Multi draw test count"));
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Multi draw test encoder") });
+    {
+        let mut render_pass = RenderPassBuilder::new()
+            .with_label("Multi draw test pass")
+            .with_color(&target.view, wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT), wgpu::StoreOp::Store)
+            .begin(&mut encoder);
+        multi_draw_indirect(&device, &mut render_pass, &[], &[], &render_object.pipeline, &draw_buffer,
+                            &indirect_buffer, 0, Some((&count_buffer, 0)), 4);
+    }
+    queue.submit(Some(encoder.finish()));
+
+    let texels: Vec<u8> = pollster::block_on(target.to_vec(&device, &queue));
+    let red: Vec<u8> = texels.chunks_exact(4).map(|t| t[0]).collect();
+    assert_eq!(red, vec![255, 255, 0, 255]);
+}
@@ -0,0 +1,42 @@
+#![cfg(target_arch = "wasm32")]
+
+use wasm_bindgen_test::*;
+use ufo3000_core::buffer::{buffer_from_data, to_vec};
+use ufo3000_core::template::{WGPUFeatures, HeadlessConfiguration, setup_headless};
+
+// Runs under node (wasm-pack test --node) or a browser (wasm-pack test --chrome). There is no
+// WebGPU in node, so the test skips there. In a browser with WebGPU a setup failure is an
+// error. The map_async channel itself is tested without an adapter in readback.rs.
+
+struct ReadbackFeatures {}
+
+impl WGPUFeatures for ReadbackFeatures {}
+
+/// Is navigator.gpu available.
+fn has_webgpu() -> bool {
+    let navigator = js_sys::Reflect::get(&js_sys::global(), &"navigator".into()).unwrap_or(wasm_bindgen::JsValue::UNDEFINED);
+    navigator.is_object() && js_sys::Reflect::has(&navigator, &"gpu".into()).unwrap_or(false)
+}
+
+#[wasm_bindgen_test]
+async fn buffer_to_vec() {
+
+    let HeadlessConfiguration { device, queue, .. } = match setup_headless::<ReadbackFeatures>(false).await {
+        Ok(configuration) => configuration,
+        Err(e) if !has_webgpu() => {
+            console_log!("Skipping buffer_to_vec, no WebGPU: {}", e);
+            return;
+        }
+        Err(e) => panic!("{}", e),
+    };
+
+    let buffer = buffer_from_data::<u32>(
+        &device,
+        &[1, 2, 3, 4],
+        wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        Some("readback test buffer"),
+    );
+
+    let result = to_vec::<u32>(&device, &queue, &buffer, 4, 8).await;
+    assert_eq!(result, vec![2, 3]);
+}